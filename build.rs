@@ -0,0 +1,20 @@
+//! Records the commit cargo-v5 is built from, which `cargo v5 version` prints.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    // Builds from a published crate have no git repository, so they have no commit.
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=CARGO_V5_COMMIT={}", commit.trim());
+    }
+}
@@ -1,22 +1,180 @@
+use humansize::{BINARY, format_size};
+use log::{debug, warn};
 use object::{Object, ObjectSection, ObjectSegment};
-use rustc_version::Channel;
-use std::process::{Stdio, exit};
+use rustc_version::{Channel, VersionMeta};
+use std::{
+    collections::BTreeSet,
+    io::{ErrorKind, IsTerminal},
+    process::Stdio,
+};
 use tokio::task::block_in_place;
 
 use cargo_metadata::{
-    Message, PackageId,
+    Message, PackageId, Target,
     camino::{Utf8Path, Utf8PathBuf},
+    diagnostic::DiagnosticLevel,
 };
 use clap::Args;
 use fs_err::tokio as fs;
 
-use crate::errors::CliError;
+use crate::{errors::CliError, metadata::Metadata};
 
 pub const TARGET_PATH: &str = "armv7a-vex-v5.json";
 
+/// Where `cargo v5 target eject` puts the target spec by default.
+///
+/// The file name must stay the same as [`TARGET_PATH`], since cargo names the target's
+/// output directory after it.
+pub const EJECTED_TARGET_PATH: &str = "v5-target/armv7a-vex-v5.json";
+
+/// Where `cargo v5 target install` puts the target spec.
+pub const INSTALLED_TARGET_PATH: &str = ".cargo/armv7a-vex-v5.json";
+
+/// The project's cargo config, where `cargo v5 target install` sets the build target.
+pub const CARGO_CONFIG_PATH: &str = ".cargo/config.toml";
+
+/// The largest program binary `--check-size` allows by default, which is about how much
+/// space the brain has for a user program.
+pub const DEFAULT_MAX_BINARY_SIZE: u64 = 0x200000;
+
+/// Flags that cargo-v5 passes to cargo itself, which conflict with the same flags in
+/// forwarded cargo options.
+const MANAGED_CARGO_FLAGS: &[&str] = &[
+    "--target",
+    "-Zbuild-std",
+    "-Zbuild-std-features",
+    "--message-format",
+];
+
+/// Environment variables that change how cargo builds, printed by `--dry-run` when set.
+const DRY_RUN_ENV_VARS: &[&str] = &[
+    "CARGO",
+    "RUSTC",
+    "RUSTC_WRAPPER",
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "CARGO_TARGET_DIR",
+    "RUSTUP_TOOLCHAIN",
+];
+
 /// Common Cargo options to forward.
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone, Default)]
 pub struct CargoOpts {
+    /// Build the example with this name instead of the package's binary.
+    #[arg(long, value_name = "NAME")]
+    pub example: Option<String>,
+
+    /// Space or comma separated list of features to activate.
+    #[arg(short = 'F', long, value_name = "FEATURES")]
+    pub features: Vec<String>,
+
+    /// Activate all available features.
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature.
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Number of parallel jobs cargo may run, like `cargo build --jobs`.
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Write an HTML report of how long each crate took to compile.
+    ///
+    /// Cargo saves the report to `target/cargo-timings/` and prints where it is.
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Set an environment variable for cargo and the build scripts it runs. May be repeated.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub envs: Vec<(String, String)>,
+
+    /// Enable a `#[cfg(...)]` flag when compiling, like `competition_mode` or
+    /// `mode="practice"`. May be repeated.
+    #[arg(long = "cfg", value_name = "KEY[=VALUE]", value_parser = parse_cfg)]
+    pub cfgs: Vec<String>,
+
+    /// Override a cargo config value, like cargo's `--config KEY=VALUE`. May be repeated.
+    #[arg(long = "config", value_name = "KEY=VALUE", value_parser = parse_config)]
+    pub configs: Vec<String>,
+
+    /// Path to the project's Cargo.toml [default: <PATH>/Cargo.toml]
+    #[arg(long, value_name = "PATH")]
+    pub manifest_path: Option<Utf8PathBuf>,
+
+    /// Write `<name>.bin` files to this directory instead of next to the ELF, along with a
+    /// copy of each ELF named `<name>.elf`.
+    #[arg(long, visible_alias = "out-dir", value_name = "DIR")]
+    pub output_dir: Option<Utf8PathBuf>,
+
+    /// Fail if a program binary is too large to fit on the brain.
+    #[arg(long)]
+    pub check_size: bool,
+
+    /// The largest program binary `--check-size` allows, in bytes [default: 2097152 (2 MiB)]
+    #[arg(long, value_name = "BYTES", requires = "check_size")]
+    pub max_binary_size: Option<u64>,
+
+    /// Build with this target spec instead of the built-in one [default: <PATH>/v5-target/armv7a-vex-v5.json, if it exists]
+    #[arg(long, value_name = "PATH")]
+    pub target_spec: Option<Utf8PathBuf>,
+
+    /// Link with this linker script instead of the one from the V5 target spec.
+    #[arg(long, value_name = "PATH")]
+    pub linker_script: Option<Utf8PathBuf>,
+
+    /// Leave executables as ELFs instead of converting them to `.bin` files.
+    ///
+    /// Set by `cargo v5 build --no-strip` or `package.metadata.v5.strip = false`.
+    #[arg(skip)]
+    pub no_strip: bool,
+
+    /// Show cargo's verbose, human-readable output instead of reading its JSON messages.
+    ///
+    /// Set by `cargo v5 build --cargo-verbose`. Without the JSON messages, the built
+    /// executables can't be found, so they aren't converted to `.bin` files.
+    #[arg(skip)]
+    pub cargo_verbose: bool,
+
+    /// Keep building after a package or target fails, and convert whatever did build.
+    ///
+    /// Set by `cargo v5 build --keep-going`.
+    #[arg(skip)]
+    pub keep_going: bool,
+
+    /// Have rustc write an assembly listing of each executable it builds.
+    ///
+    /// Set by `cargo v5 build --emit-asm`.
+    #[arg(skip)]
+    pub emit_asm: bool,
+
+    /// Print only this function's assembly from the listings, instead of where they are.
+    ///
+    /// Set by `cargo v5 build --function`.
+    #[arg(skip)]
+    pub asm_function: Option<String>,
+
+    /// Print the cargo command instead of running it.
+    ///
+    /// Set by `cargo v5 build --dry-run`. The target spec is still written, so the printed
+    /// command can be run as-is.
+    #[arg(skip)]
+    pub dry_run: bool,
+
+    /// Build even if the toolchain doesn't report itself as nightly, for nightly-based
+    /// toolchains that don't. vexide still needs nightly features.
+    ///
+    /// Can also be set with `CARGO_V5_NO_NIGHTLY_CHECK=1`.
+    #[arg(long)]
+    pub no_nightly_check: bool,
+
+    /// Fail instead of warning when the forwarded cargo options contain flags cargo-v5
+    /// already passes, like `--target` or `-Zbuild-std`.
+    #[arg(long)]
+    pub strict: bool,
+
     /// Arguments forwarded to cargo.
     #[arg(
         trailing_var_arg = true,
@@ -26,50 +184,308 @@ pub struct CargoOpts {
     args: Vec<String>,
 }
 
+/// Parses a `KEY=VALUE` argument.
+fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected `KEY=VALUE`, found `{arg}`")),
+    }
+}
+
+/// Parses a `--cfg` argument into rustc's `key` or `key="value"` form.
+fn parse_cfg(arg: &str) -> Result<String, String> {
+    let (key, value) = match arg.split_once('=') {
+        Some((key, value)) => (key, Some(value.trim_matches('"'))),
+        None => (arg, None),
+    };
+
+    let is_ident = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_ident {
+        return Err(format!("expected `KEY` or `KEY=VALUE`, found `{arg}`"));
+    }
+
+    Ok(match value {
+        Some(value) => format!("{key}={value:?}"),
+        None => key.to_string(),
+    })
+}
+
+/// Checks that a `--config` argument has a `KEY=VALUE` shape.
+fn parse_config(arg: &str) -> Result<String, String> {
+    parse_key_value(arg).map(|_| arg.to_string())
+}
+
+/// Finds the forwarded cargo option that sets one of the [`MANAGED_CARGO_FLAGS`], if any
+/// does.
+fn managed_cargo_flag(args: &[String]) -> Option<String> {
+    let mut args = args.iter().take_while(|arg| *arg != "--").peekable();
+    while let Some(arg) = args.next() {
+        // `-Z build-std` is the same as `-Zbuild-std`.
+        let flag = match (arg.as_str(), args.peek()) {
+            ("-Z", Some(next)) => format!("-Z{next}"),
+            _ => arg.clone(),
+        };
+        let is_managed = MANAGED_CARGO_FLAGS.iter().any(|managed| {
+            flag.strip_prefix(managed)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+        });
+        if is_managed {
+            return Some(flag);
+        }
+    }
+    None
+}
+
+impl CargoOpts {
+    /// The manifest to build, either from `--manifest-path` or the one in `path`.
+    pub fn manifest_path(&self, path: &Utf8Path) -> Utf8PathBuf {
+        self.manifest_path
+            .clone()
+            .unwrap_or_else(|| path.join("Cargo.toml"))
+    }
+
+    /// Builds only the binary target named `bin`.
+    pub fn bin(&mut self, bin: &str) {
+        self.args
+            .splice(0..0, ["--bin".to_string(), bin.to_string()]);
+    }
+}
+
+/// A `rustc` command for the toolchain that builds the project at `path`.
+///
+/// This runs from the same directory cargo runs in, so the `rust-toolchain.toml` that
+/// cargo sees is respected.
+pub fn rustc_cmd(path: &Utf8Path) -> std::process::Command {
+    let mut rustc_cmd =
+        std::process::Command::new(std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()));
+    rustc_cmd.current_dir(path);
+    rustc_cmd
+}
+
+/// Whether the toolchain that builds the project at `path` is a nightly one, which vexide
+/// needs for `-Zbuild-std`.
+pub fn is_nightly_toolchain(path: &Utf8Path) -> bool {
+    VersionMeta::for_command(rustc_cmd(path))
+        .is_ok_and(|meta| matches!(meta.channel, Channel::Nightly | Channel::Dev))
+}
+
+/// Finds a JSON target spec set as `build.target` in the project's cargo config, such as
+/// one written by `cargo v5 target install`.
+///
+/// The generated [`TARGET_PATH`] spec that vexide-template configures isn't counted, since
+/// cargo-v5 keeps that one up to date with the toolchain.
+pub fn configured_target_spec(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let config = [CARGO_CONFIG_PATH, ".cargo/config"]
+        .iter()
+        .find_map(|config| std::fs::read_to_string(path.join(config)).ok())?;
+    let config = config.parse::<toml_edit::DocumentMut>().ok()?;
+    let target = config.get("build")?.get("target")?.as_str()?;
+
+    // Relative paths in `build.target` are relative to the directory containing `.cargo`.
+    let target = path.join(target);
+    (target.extension() == Some("json") && target != path.join(TARGET_PATH) && target.exists())
+        .then_some(target)
+}
+
+/// Whether any `target.<triple>.rustflags` or `target.'cfg(..)'.rustflags` are configured for
+/// a build in `path`, which make cargo ignore `build.rustflags`.
+///
+/// This checks the cargo config files cargo would read from `path`, the
+/// `CARGO_TARGET_<TRIPLE>_RUSTFLAGS` environment variables, and `configs` from `--config`.
+fn target_rustflags_configured(path: &Utf8Path, configs: &[String]) -> bool {
+    if std::env::vars_os().any(|(var, _)| {
+        var.to_str()
+            .is_some_and(|var| var.starts_with("CARGO_TARGET_") && var.ends_with("_RUSTFLAGS"))
+    }) {
+        return true;
+    }
+
+    if configs
+        .iter()
+        .any(|config| config.starts_with("target.") && config.contains("rustflags"))
+    {
+        return true;
+    }
+
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|home| home.join(".cargo")));
+    let config_dirs = path
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home);
+
+    config_dirs
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter_map(|config| std::fs::read_to_string(config).ok())
+        .filter_map(|config| config.parse::<toml_edit::DocumentMut>().ok())
+        .any(|config| {
+            config
+                .get("target")
+                .and_then(|targets| targets.as_table_like())
+                .is_some_and(|targets| {
+                    targets
+                        .iter()
+                        .any(|(_, target)| target.get("rustflags").is_some())
+                })
+        })
+}
+
+/// The built-in target spec that works with `rustc_version_meta`'s toolchain.
+pub fn builtin_target_spec(rustc_version_meta: &VersionMeta) -> &'static str {
+    // rustc 1.91.0 made a breaking change to the target spec format.
+    //
+    // NOTE: 1.91.0 nightlies before 2025-08-31 will still break with this check,
+    // however my ass is not about to parse dates to determine that so I don't care.
+    if rustc_version_meta.semver.major >= 1 && rustc_version_meta.semver.minor >= 91 {
+        include_str!("../targets/armv7a-vex-v5.json")
+    } else {
+        include_str!("../targets/armv7a-vex-v5-old.json")
+    }
+}
+
+/// The target directory cargo builds `manifest_path` into from `path`, which honors
+/// `CARGO_TARGET_DIR`, `build.target-dir`, and `configs` from `--config`.
+fn target_directory(
+    path: &Utf8Path,
+    manifest_path: &Utf8Path,
+    configs: &[String],
+) -> Result<Utf8PathBuf, CliError> {
+    let metadata = block_in_place(|| {
+        cargo_metadata::MetadataCommand::new()
+            .current_dir(path)
+            .manifest_path(manifest_path)
+            .no_deps()
+            .other_options(
+                configs
+                    .iter()
+                    .flat_map(|config| ["--config".to_string(), config.clone()])
+                    .collect::<Vec<_>>(),
+            )
+            .exec()
+    })
+    .map_err(CliError::CargoMetadataError)?;
+
+    Ok(metadata.target_directory)
+}
+
 pub fn cargo_bin() -> std::ffi::OsString {
     std::env::var_os("CARGO").unwrap_or_else(|| "cargo".to_owned().into())
 }
 
+#[derive(Debug)]
 pub struct BuildOutput {
     pub elf_artifact: Utf8PathBuf,
+    /// The program binary, or the ELF itself if the build was run with `no_strip`.
     pub bin_artifact: Utf8PathBuf,
     pub package_id: PackageId,
+    pub manifest_path: Utf8PathBuf,
 }
 
-pub async fn build(path: &Utf8Path, opts: CargoOpts) -> miette::Result<Option<BuildOutput>> {
-    let rustc_version_meta =
-        rustc_version::version_meta().map_err(|e| CliError::RustcVersionError(e))?;
-    let target_path = path.join(TARGET_PATH);
+/// Builds the project at `path` for the V5, returning an output for each executable that was
+/// built, in the order cargo reported them.
+///
+/// Nothing is returned for `--dry-run` and `--cargo-verbose` builds, which don't convert
+/// executables.
+pub async fn build(path: &Utf8Path, opts: CargoOpts) -> Result<Vec<BuildOutput>, CliError> {
+    let manifest_path = opts.manifest_path(path);
+    if !manifest_path.exists() {
+        Err(CliError::ManifestNotFound(manifest_path.clone()))?;
+    }
+
+    if let Some(flag) = managed_cargo_flag(&opts.args) {
+        if opts.strict {
+            return Err(CliError::ManagedCargoFlag(flag));
+        }
+        warn!("`{flag}` conflicts with a flag cargo-v5 passes to cargo, so the build may fail.");
+    }
+
+    let rustc_version_meta = rustc_version::VersionMeta::for_command(rustc_cmd(path))
+        .map_err(|e| CliError::RustcVersionError(e))?;
+    // A user's own target spec (from `--target-spec`, `cargo v5 target eject`, or
+    // `cargo v5 target install`) is used as-is rather than being overwritten.
+    let custom_target_path = match &opts.target_spec {
+        Some(target_spec) => Some(target_spec.clone()),
+        None => Some(path.join(EJECTED_TARGET_PATH))
+            .filter(|ejected| ejected.exists())
+            .or_else(|| configured_target_spec(path)),
+    };
+    let custom_target = match &custom_target_path {
+        Some(custom_target_path) => {
+            debug!("Using target spec {custom_target_path}");
+            Some(
+                fs::read_to_string(custom_target_path)
+                    .await
+                    .map_err(CliError::from)?,
+            )
+        }
+        None => None,
+    };
+
+    // A modified target spec is kept out of the project directory so the canonical
+    // `armv7a-vex-v5.json` doesn't get overwritten with a user's customizations. The file
+    // name has to stay the same, since cargo names the target's output directory after it.
+    let target_path = match (&opts.linker_script, &custom_target_path) {
+        (Some(_), _) => target_directory(path, &manifest_path, &opts.configs)?
+            .join("cargo-v5")
+            .join(TARGET_PATH),
+        (None, Some(custom_target_path)) => custom_target_path.clone(),
+        (None, None) => path.join(TARGET_PATH),
+    };
     let mut build_cmd = std::process::Command::new(cargo_bin());
     build_cmd
         .current_dir(path)
         .arg("build")
         .arg("--message-format")
-        .arg("json-render-diagnostics");
+        .arg(if opts.cargo_verbose {
+            "human"
+        } else if opts.keep_going {
+            // Diagnostics are rendered by cargo-v5 instead, since the targets they're for
+            // are how failed targets are found.
+            if std::io::stderr().is_terminal() {
+                "json-diagnostic-rendered-ansi"
+            } else {
+                "json"
+            }
+        } else {
+            "json-render-diagnostics"
+        });
 
     if !matches!(rustc_version_meta.channel, Channel::Nightly | Channel::Dev) {
-        eprintln!("ERROR: vexide requires Nightly Rust features, but you're using stable.");
-        eprintln!(" hint: this can be fixed by running `rustup override set nightly`");
-        exit(1);
+        if !opts.no_nightly_check {
+            Err(CliError::NightlyRequired)?;
+        }
+        warn!(
+            "Skipping the nightly toolchain check. vexide still needs nightly features, so the build may fail."
+        );
     }
 
     if !target_path.exists() {
-        fs::create_dir_all(target_path.parent().unwrap())
-            .await
-            .unwrap();
+        fs::create_dir_all(target_path.parent().unwrap()).await?;
     }
 
-    // rustc 1.91.0 made a breaking change to the target spec format.
-    //
-    // NOTE: 1.91.0 nightlies before 2025-08-31 will still break with this check,
-    // however my ass is not about to parse dates to determine that so I don't care.
-    let target = if rustc_version_meta.semver.major >= 1 && rustc_version_meta.semver.minor >= 91 {
-        include_str!("../targets/armv7a-vex-v5.json")
-    } else {
-        include_str!("../targets/armv7a-vex-v5-old.json")
-    };
+    let target = custom_target
+        .as_deref()
+        .unwrap_or_else(|| builtin_target_spec(&rustc_version_meta));
 
-    fs::write(&target_path, target).await.unwrap();
+    if let Some(linker_script) = &opts.linker_script {
+        let linker_script = fs::canonicalize(linker_script)
+            .await
+            .map_err(CliError::from)?;
+        debug!("Overriding linker script with {}", linker_script.display());
+        let spec_path = custom_target_path
+            .as_deref()
+            .unwrap_or(Utf8Path::new(TARGET_PATH));
+        fs::write(
+            &target_path,
+            override_linker_script(target, spec_path, &linker_script.to_string_lossy())?,
+        )
+        .await?;
+    } else if custom_target.is_none() {
+        fs::write(&target_path, target).await?;
+    }
 
     build_cmd
         .arg("--target")
@@ -78,45 +494,594 @@ pub async fn build(path: &Utf8Path, opts: CargoOpts) -> miette::Result<Option<Bu
         .arg("-Zbuild-std-features=compiler-builtins-mem")
         .stdout(Stdio::piped());
 
+    if opts.manifest_path.is_some() {
+        build_cmd.arg("--manifest-path").arg(&manifest_path);
+    }
+
+    if let Some(example) = &opts.example {
+        build_cmd.arg("--example").arg(example);
+    }
+
+    for features in &opts.features {
+        build_cmd.arg("--features").arg(features);
+    }
+    if opts.all_features {
+        build_cmd.arg("--all-features");
+    }
+    if opts.no_default_features {
+        build_cmd.arg("--no-default-features");
+    }
+    if let Some(jobs) = opts.jobs {
+        build_cmd.arg("--jobs").arg(jobs.to_string());
+    }
+    // This only asks for the HTML report. A JSON one would be mixed in with the build
+    // messages read from stdout.
+    if opts.timings {
+        build_cmd.arg("--timings");
+    }
+    if opts.keep_going {
+        build_cmd.arg("--keep-going");
+    }
+    if opts.cargo_verbose {
+        build_cmd.arg("--verbose");
+    }
+
+    if !opts.cfgs.is_empty() || opts.emit_asm {
+        // Cargo ignores `build.rustflags` when `RUSTFLAGS` is set.
+        if ["RUSTFLAGS", "CARGO_ENCODED_RUSTFLAGS"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some())
+        {
+            warn!(
+                "`--cfg` and `--emit-asm` have no effect because the RUSTFLAGS environment variable is set."
+            );
+        }
+
+        // rustc adds this to the outputs cargo asks for, so the executable is still linked.
+        let rustflags = opts
+            .cfgs
+            .iter()
+            .map(|cfg| format!("--cfg={cfg}"))
+            .chain(opts.emit_asm.then(|| "--emit=asm".to_string()))
+            .collect::<toml_edit::Array>();
+        // Cargo ignores `build.rustflags` once any target-level rustflags are configured, so
+        // the flags are added to the target's instead, which cargo merges with them. The
+        // target's name is its spec's file name.
+        let rustflags_key = if target_rustflags_configured(path, &opts.configs) {
+            format!(
+                "target.{}.rustflags",
+                target_path.file_stem().unwrap_or("armv7a-vex-v5")
+            )
+        } else {
+            "build.rustflags".to_string()
+        };
+        build_cmd
+            .arg("--config")
+            .arg(format!("{rustflags_key}={rustflags}"));
+    }
+
+    // These come after everything cargo-v5 passes, so that they take precedence.
+    for config in &opts.configs {
+        build_cmd.arg("--config").arg(config);
+    }
+
+    build_cmd.envs(opts.envs.iter().map(|(key, value)| (key, value)));
     build_cmd.args(opts.args);
 
-    Ok(block_in_place::<_, Result<Option<BuildOutput>, CliError>>(
-        || {
-            let mut out = build_cmd.spawn()?;
-            let reader = std::io::BufReader::new(out.stdout.take().unwrap());
-
-            let mut output = None;
-
-            for message in Message::parse_stream(reader) {
-                match message? {
-                    Message::CompilerArtifact(artifact) => {
-                        if let Some(elf_artifact_path) = artifact.executable {
-                            let binary = objcopy(&std::fs::read(&elf_artifact_path)?)?;
-                            let binary_path = elf_artifact_path.with_extension("bin");
-
-                            // Write the binary to a file.
-                            std::fs::write(&binary_path, binary)?;
-                            println!("     \x1b[1;92mObjcopy\x1b[0m {}", binary_path);
-
-                            output = Some(BuildOutput {
-                                bin_artifact: binary_path,
-                                elf_artifact: elf_artifact_path,
-                                package_id: artifact.package_id,
-                            });
-                        }
+    if opts.dry_run {
+        // The build inherits these, so they explain most differences from a hand-run cargo.
+        for var in DRY_RUN_ENV_VARS {
+            if let Some(value) = std::env::var_os(var) {
+                println!("{var}={value:?}");
+            }
+        }
+        println!("{build_cmd:?}");
+        if !opts.no_strip {
+            println!(
+                "Each executable would then be converted from ELF to BIN (objcopy -O binary)."
+            );
+        }
+        return Ok(Vec::new());
+    }
+
+    if opts.cargo_verbose {
+        let status = block_in_place(|| build_cmd.stdout(std::io::stderr()).status())?;
+        if !status.success() {
+            return Err(CliError::BuildFailed);
+        }
+
+        eprintln!(
+            "Skipping objcopy, since `--cargo-verbose` doesn't say which executables were built."
+        );
+        return Ok(Vec::new());
+    }
+
+    let (executables, targets, success) = block_in_place::<_, Result<_, CliError>>(|| {
+        let mut out = build_cmd.spawn()?;
+        let reader = std::io::BufReader::new(out.stdout.take().unwrap());
+
+        let mut executables = Vec::new();
+        let mut targets = TargetSummary::default();
+
+        for message in Message::parse_stream(reader) {
+            match message? {
+                Message::CompilerArtifact(artifact) => {
+                    // When building an example, other targets' executables aren't what we want.
+                    if let Some(example) = &opts.example
+                        && !(artifact.target.is_example() && &artifact.target.name == example)
+                    {
+                        continue;
+                    }
+
+                    if let Some(elf_artifact_path) = artifact.executable {
+                        targets.built.push(target_label(&artifact.target));
+                        executables.push((
+                            elf_artifact_path,
+                            artifact.package_id,
+                            artifact.manifest_path,
+                        ));
                     }
-                    _ => {}
                 }
+                Message::CompilerMessage(message) => {
+                    if let Some(rendered) = &message.message.rendered {
+                        eprint!("{rendered}");
+                    }
+                    if matches!(
+                        message.message.level,
+                        DiagnosticLevel::Error | DiagnosticLevel::Ice
+                    ) {
+                        targets.failed.insert(target_label(&message.target));
+                    }
+                }
+                _ => {}
             }
+        }
+
+        let status = out.wait()?;
+        // With `--keep-going`, the executables that did build are still worth converting.
+        if !status.success() && !opts.keep_going {
+            return Err(CliError::BuildFailed);
+        }
+
+        Ok((executables, targets, status.success()))
+    })?;
+
+    if opts.emit_asm {
+        for (elf_artifact_path, ..) in &executables {
+            print_assembly(elf_artifact_path, opts.asm_function.as_deref())?;
+        }
+    }
+
+    if let Some(output_dir) = &opts.output_dir {
+        fs::create_dir_all(output_dir)
+            .await
+            .map_err(|err| output_error(err, output_dir))?;
+    }
+
+    // Each executable is independent, so they're all converted concurrently.
+    let objcopy_tasks = executables
+        .into_iter()
+        .map(|(elf_artifact_path, package_id, manifest_path)| {
+            let output_dir = opts.output_dir.clone();
+            let no_strip = opts.no_strip;
+            let max_binary_size = opts
+                .check_size
+                .then(|| opts.max_binary_size.unwrap_or(DEFAULT_MAX_BINARY_SIZE));
+            tokio::task::spawn_blocking(move || -> Result<BuildOutput, CliError> {
+                let binary_path = match &output_dir {
+                    Some(output_dir) => {
+                        let name = elf_artifact_path.file_name().unwrap_or_default();
+
+                        // Keep a copy of the ELF next to the binary, so both can be archived
+                        // from one predictable place.
+                        let elf_copy_path = output_dir.join(name).with_extension("elf");
+                        std::fs::copy(&elf_artifact_path, &elf_copy_path)
+                            .map_err(|err| output_error(err, &elf_copy_path))?;
+
+                        output_dir.join(name).with_extension("bin")
+                    }
+                    None => elf_artifact_path.with_extension("bin"),
+                };
+
+                if no_strip {
+                    eprintln!("    \x1b[1;92mArtifact\x1b[0m {elf_artifact_path}");
+                    if let Some(limit) = max_binary_size {
+                        // The brain is only ever sent the binary, so that's what gets checked.
+                        let binary = objcopy(&std::fs::read(&elf_artifact_path)?)?;
+                        check_binary_size(&elf_artifact_path, binary.len() as u64, limit)?;
+                    }
 
-            let status = out.wait()?;
-            if !status.success() {
-                exit(status.code().unwrap_or(1));
+                    return Ok(BuildOutput {
+                        bin_artifact: elf_artifact_path.clone(),
+                        elf_artifact: elf_artifact_path,
+                        package_id,
+                        manifest_path,
+                    });
+                }
+
+                // Write the binary to a file.
+                let binary = objcopy(&std::fs::read(&elf_artifact_path)?)?;
+                let binary_size = binary.len() as u64;
+                std::fs::write(&binary_path, binary)
+                    .map_err(|err| output_error(err, &binary_path))?;
+                eprintln!("     \x1b[1;92mObjcopy\x1b[0m {}", binary_path);
+
+                if let Some(limit) = max_binary_size {
+                    check_binary_size(&binary_path, binary_size, limit)?;
+                }
+
+                Ok(BuildOutput {
+                    bin_artifact: binary_path,
+                    elf_artifact: elf_artifact_path,
+                    package_id,
+                    manifest_path,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut outputs = Vec::new();
+    for task in objcopy_tasks {
+        outputs.push(task.await??);
+    }
+
+    if !success {
+        for target in &targets.built {
+            eprintln!("       \x1b[1;92mBuilt\x1b[0m {target}");
+        }
+        for target in &targets.failed {
+            eprintln!("      \x1b[1;91mFailed\x1b[0m {target}");
+        }
+        return Err(CliError::BuildFailed);
+    }
+
+    Ok(outputs)
+}
+
+/// The only output of a build, for commands like uploads that need exactly one program.
+pub fn single_output(outputs: Vec<BuildOutput>) -> Result<BuildOutput, CliError> {
+    match <[BuildOutput; 1]>::try_from(outputs) {
+        Ok([output]) => Ok(output),
+        Err(outputs) if outputs.is_empty() => Err(CliError::NoArtifact),
+        Err(outputs) => Err(CliError::MultipleArtifacts(
+            outputs
+                .iter()
+                .map(|output| {
+                    output
+                        .bin_artifact
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect(),
+        )),
+    }
+}
+
+/// The targets that a failed build did and didn't build, printed as a summary.
+#[derive(Default)]
+struct TargetSummary {
+    built: Vec<String>,
+    /// Targets that rustc reported errors for. Only found with `--keep-going`, which has
+    /// cargo send diagnostics as messages.
+    failed: BTreeSet<String>,
+}
+
+/// A target's name and kind for build summaries, like `autonomous (bin)`.
+fn target_label(target: &Target) -> String {
+    match target.kind.first() {
+        Some(kind) => format!("{} ({kind})", target.name),
+        None => target.name.to_string(),
+    }
+}
+
+/// Prints where the assembly listing for the executable at `elf_artifact_path` is, or with
+/// `function`, the assembly of each function with that name or path.
+fn print_assembly(elf_artifact_path: &Utf8Path, function: Option<&str>) -> Result<(), CliError> {
+    let Some(listing_path) = find_assembly_listing(elf_artifact_path)? else {
+        return Err(CliError::AsmListingNotFound(elf_artifact_path.to_owned()));
+    };
+
+    let Some(function) = function else {
+        eprintln!("    \x1b[1;92mAssembly\x1b[0m {listing_path}");
+        return Ok(());
+    };
+
+    let listing = std::fs::read_to_string(&listing_path)?;
+    let sections = function_assembly(&listing, function);
+    if sections.is_empty() {
+        return Err(CliError::AsmFunctionNotFound {
+            function: function.to_string(),
+            listing: listing_path,
+        });
+    }
+    for section in sections {
+        println!("{section}");
+    }
+
+    Ok(())
+}
+
+/// Finds the `.s` file rustc wrote for the executable at `elf_artifact_path` when it last
+/// compiled it.
+///
+/// Cargo only copies the executable itself out of its build directory, so rustc's dep-info
+/// file for it is searched for next to where rustc built it. That's `deps/<crate>-<hash>.d`,
+/// or `build/<package>/<hash>/out/<crate>.d` with cargo's newer build directory layout. The
+/// newest one lists the listing as an output only if `--emit=asm` reached rustc, so a
+/// listing left over from an earlier build isn't mistaken for the current one.
+fn find_assembly_listing(elf_artifact_path: &Utf8Path) -> Result<Option<Utf8PathBuf>, CliError> {
+    let Some(mut profile_dir) = elf_artifact_path.parent() else {
+        return Ok(None);
+    };
+    // Examples are built in `examples/` instead of `deps/`, and are searched from its parent.
+    if profile_dir.file_name() == Some("examples") {
+        profile_dir = profile_dir.parent().unwrap_or(profile_dir);
+    }
+    let crate_name = elf_artifact_path
+        .file_stem()
+        .unwrap_or_default()
+        .replace('-', "_");
+
+    // Cargo writes its own dep-info file next to the copy of the executable.
+    let cargo_dep_info = elf_artifact_path.with_extension("d");
+
+    let mut newest = None;
+    let mut dirs = vec![profile_dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = dir.read_dir_utf8() else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                if entry.file_name() != "incremental" {
+                    dirs.push(path.to_owned());
+                }
+                continue;
+            }
+
+            let is_dep_info = path.extension() == Some("d")
+                && path != cargo_dep_info
+                && path.file_stem().is_some_and(|stem| {
+                    stem == crate_name
+                        || stem
+                            .strip_prefix(&crate_name)
+                            .is_some_and(|hash| hash.starts_with('-'))
+                });
+            if !is_dep_info {
+                continue;
+            }
+
+            let modified = entry.metadata()?.modified()?;
+            if newest.as_ref().is_none_or(|(newest, _)| modified > *newest) {
+                newest = Some((modified, path.to_owned()));
+            }
+        }
+    }
+
+    let Some((_, dep_info_path)) = newest else {
+        return Ok(None);
+    };
+
+    // Each output is listed as a `<path>: <sources>` rule, with spaces in paths escaped.
+    let dep_info = std::fs::read_to_string(dep_info_path)?;
+    Ok(dep_info
+        .lines()
+        .filter_map(|line| line.split_once(": ").map(|(output, _)| output))
+        .find(|output| output.ends_with(".s"))
+        .map(|output| Utf8PathBuf::from(output.replace("\\ ", " "))))
+}
+
+/// Extracts the assembly of each function in `listing` named `function`.
+///
+/// Symbols are demangled first, so `function` can be a bare name like `autonomous` or a
+/// path like `my_robot::autonomous`. Generic functions may have several matches.
+fn function_assembly(listing: &str, function: &str) -> Vec<String> {
+    let lines = listing.lines().collect::<Vec<_>>();
+    let mut sections = Vec::new();
+
+    for (start, line) in lines.iter().enumerate() {
+        let Some(symbol) = line
+            .trim()
+            .strip_prefix(".type")
+            .and_then(|rest| rest.trim().strip_suffix(",%function"))
+        else {
+            continue;
+        };
+
+        let demangled = format!("{:#}", rustc_demangle::demangle(symbol));
+        let matches = symbol == function
+            || demangled == function
+            || demangled
+                .strip_suffix(function)
+                .is_some_and(|path| path.ends_with("::"));
+        if !matches {
+            continue;
+        }
+
+        let mut end = lines[start..]
+            .iter()
+            .position(|line| {
+                line.trim()
+                    .strip_prefix(".size")
+                    .is_some_and(|rest| rest.trim_start().starts_with(&format!("{symbol},")))
+            })
+            .map_or(lines.len() - 1, |offset| start + offset);
+        // The unwind directives after `.size` still belong to the function.
+        while lines
+            .get(end + 1)
+            .is_some_and(|line| matches!(line.trim(), ".cfi_endproc" | ".fnend"))
+        {
+            end += 1;
+        }
+
+        sections.push(format!("{demangled}:\n{}", lines[start..=end].join("\n")));
+    }
+
+    sections
+}
+
+/// Turns a failure to write build output to `path` into a [`CliError`], pointing out
+/// `--output-dir` if the location isn't writable (e.g. a read-only mount).
+fn output_error(err: std::io::Error, path: &Utf8Path) -> CliError {
+    match err.kind() {
+        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => {
+            CliError::OutputNotWritable(path.to_owned())
+        }
+        _ => CliError::IoError(err),
+    }
+}
+
+/// Replaces the `-T` linker script argument in a target spec with `linker_script`.
+///
+/// Existing `-T` arguments are removed from both `pre-link-args` and `post-link-args`, and
+/// the new one takes the place of the first one found (or is added to `pre-link-args`).
+///
+/// `target` may be a user's own spec, read from `spec_path`, so it isn't assumed to be valid.
+fn override_linker_script(
+    target: &str,
+    spec_path: &Utf8Path,
+    linker_script: &str,
+) -> Result<String, CliError> {
+    let mut spec = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(target)
+        .map_err(|source| CliError::InvalidTargetSpec {
+            path: spec_path.to_owned(),
+            source,
+        })?;
+    let script_arg = serde_json::Value::String(format!("-T{linker_script}"));
+    let mut replaced = false;
+
+    for key in ["pre-link-args", "post-link-args"] {
+        let Some(args) = spec
+            .get_mut(key)
+            .and_then(|args| args.get_mut("ld.lld"))
+            .and_then(|args| args.as_array_mut())
+        else {
+            continue;
+        };
+
+        if let Some(index) = args
+            .iter()
+            .position(|arg| arg.as_str().is_some_and(|arg| arg.starts_with("-T")))
+        {
+            args.retain(|arg| !arg.as_str().is_some_and(|arg| arg.starts_with("-T")));
+            if !replaced {
+                args.insert(index, script_arg.clone());
+                replaced = true;
             }
+        }
+    }
+
+    if !replaced {
+        let pre_link_args = spec
+            .entry("pre-link-args")
+            .or_insert_with(|| serde_json::json!({}));
+        match pre_link_args["ld.lld"].as_array_mut() {
+            Some(args) => args.push(script_arg),
+            None => pre_link_args["ld.lld"] = serde_json::json!([script_arg]),
+        }
+    }
+
+    Ok(serde_json::to_string_pretty(&spec).unwrap())
+}
+
+/// Fails with [`CliError::BinaryTooLarge`] if the program built to `path` is over `limit` bytes.
+fn check_binary_size(path: &Utf8Path, size: u64, limit: u64) -> Result<(), CliError> {
+    if size > limit {
+        return Err(CliError::BinaryTooLarge {
+            path: path.to_owned(),
+            size,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Whether the package at `manifest_path` sets `package.metadata.v5.strip = false`.
+///
+/// Workspaces without a root package, and manifests that can't be read, keep stripping on.
+pub fn strip_disabled(manifest_path: &Utf8Path) -> Result<bool, CliError> {
+    let metadata = block_in_place(|| {
+        cargo_metadata::MetadataCommand::new()
+            .manifest_path(manifest_path)
+            .no_deps()
+            .exec()
+    });
+
+    match metadata
+        .ok()
+        .as_ref()
+        .and_then(|metadata| metadata.root_package())
+    {
+        Some(package) => Ok(Metadata::new(package)?.strip == Some(false)),
+        None => Ok(false),
+    }
+}
+
+/// Build every package in the workspace that has a `[package.metadata.v5]` table.
+///
+/// Each package is built separately with `build`, then a summary of the produced binaries
+/// is printed. With `keep_going`, packages that fail to build are listed in the summary
+/// instead of stopping the other packages from building.
+pub async fn build_workspace(
+    path: &Utf8Path,
+    opts: CargoOpts,
+) -> Result<Vec<BuildOutput>, CliError> {
+    let metadata = block_in_place(|| {
+        cargo_metadata::MetadataCommand::new()
+            .manifest_path(opts.manifest_path(path))
+            .no_deps()
+            .exec()
+    })
+    .map_err(CliError::CargoMetadataError)?;
+
+    let mut outputs = Vec::new();
+    let mut failed = Vec::new();
+
+    for package in metadata.workspace_packages() {
+        if package.metadata.get("v5").is_none() {
+            debug!(
+                "Skipping {} because it has no `package.metadata.v5` table.",
+                package.name
+            );
+            continue;
+        }
+
+        let mut package_opts = opts.clone();
+        package_opts.no_strip |= Metadata::new(package)?.strip == Some(false);
+        package_opts
+            .args
+            .splice(0..0, ["--package".to_string(), package.name.to_string()]);
+
+        match build(path, package_opts).await {
+            Ok(package_outputs) => outputs.extend(package_outputs),
+            Err(CliError::BuildFailed) if opts.keep_going => failed.push(package.name.to_string()),
+            Err(err) => return Err(err),
+        }
+    }
+
+    for output in &outputs {
+        let size = std::fs::metadata(&output.bin_artifact)
+            .map(|metadata| format_size(metadata.len(), BINARY))
+            .unwrap_or_else(|_| "unknown size".to_string());
+        eprintln!(
+            "       \x1b[1;92mBuilt\x1b[0m {} ({size})",
+            output.bin_artifact
+        );
+    }
+    for package in &failed {
+        eprintln!("      \x1b[1;91mFailed\x1b[0m {package}");
+    }
+
+    if !failed.is_empty() {
+        return Err(CliError::BuildFailed);
+    }
 
-            Ok(output)
-        },
-    )?)
+    Ok(outputs)
 }
 
 /// Implementation of `objcopy -O binary`.
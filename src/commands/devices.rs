@@ -1,44 +1,122 @@
 use std::io::{self, Write};
 use std::time::Duration;
 
+use serde::Serialize;
 use vex_v5_serial::connection::{serial::SerialConnection, Connection};
 
 use tabwriter::TabWriter;
-use vex_v5_serial::packets::device::{GetDeviceStatusPacket, GetDeviceStatusReplyPacket};
+use vex_v5_serial::packets::device::{
+    DeviceStatus, DeviceType, GetDeviceStatusPacket, GetDeviceStatusReplyPacket,
+};
 
 use crate::errors::CliError;
 
-pub async fn devices(connection: &mut SerialConnection) -> Result<(), CliError> {
-    let mut tw = TabWriter::new(io::stdout());
+/// How often `--watch` asks the brain for its devices.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A device in `cargo v5 devices --json` output.
+///
+/// The field names and `kind` values are a stable schema, documented in the README.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+struct Device {
+    port: u8,
+    kind: &'static str,
+    version: String,
+    name: &'static str,
+}
+
+/// A change to the connected devices, printed by `cargo v5 devices --json --watch`.
+#[derive(Serialize)]
+struct DeviceEvent<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    device: &'a Device,
+}
+
+/// A stable identifier and a display name for each device type.
+fn device_kind(device_type: DeviceType) -> (&'static str, &'static str) {
+    match device_type {
+        DeviceType::NoSensor => ("none", "No Device"),
+        DeviceType::Motor => ("motor", "Motor"),
+        DeviceType::Led => ("led", "LED"),
+        DeviceType::AbsEncoder => ("rotation_sensor", "Rotation Sensor"),
+        DeviceType::CrMotor => ("cr_motor", "Continuous Rotation Motor"),
+        DeviceType::Imu => ("inertial_sensor", "Inertial Sensor"),
+        DeviceType::DistanceSensor => ("distance_sensor", "Distance Sensor"),
+        DeviceType::Radio => ("radio", "Radio"),
+        DeviceType::TetheredController => ("controller", "Tethered Controller"),
+        DeviceType::Brain => ("brain", "Brain"),
+        DeviceType::VisionSensor => ("vision_sensor", "Vision Sensor"),
+        DeviceType::AdiExpander => ("adi_expander", "ADI Expander"),
+        DeviceType::Res1Sensor => ("reserved_1", "Reserved Device"),
+        DeviceType::Battery => ("battery", "Battery"),
+        DeviceType::Res3Sensor => ("reserved_3", "Reserved Device"),
+        DeviceType::OpticalSensor => ("optical_sensor", "Optical Sensor"),
+        DeviceType::Magnet => ("electromagnet", "Electromagnet"),
+        DeviceType::GpsSensor => ("gps_sensor", "GPS Sensor"),
+        DeviceType::AicameraSensor => ("ai_camera", "AI Camera"),
+        DeviceType::LightTower => ("light_tower", "Light Tower"),
+        DeviceType::ArmDevice => ("arm", "CTE Arm"),
+        DeviceType::AiVisionSensor => ("ai_vision_sensor", "AI Vision Sensor"),
+        DeviceType::Pneumatic => ("pneumatics", "Pneumatics"),
+        DeviceType::BumperSensor => ("bumper_sensor", "Bumper Sensor"),
+        DeviceType::GyroSensor => ("gyro_sensor", "Gyro Sensor"),
+        DeviceType::SonarSensor => ("sonar_sensor", "Sonar Sensor"),
+        DeviceType::GenericSensor => ("generic", "Generic Device"),
+        DeviceType::GenericSerial => ("generic_serial", "Generic Serial"),
+        DeviceType::UndefinedSensor => ("unknown", "Unknown Device"),
+    }
+}
+
+fn firmware_version(device: &DeviceStatus) -> String {
+    format!(
+        "{}.{}.{}.b{}",
+        (u32::from(device.version) >> 14) as u8,
+        ((u32::from(device.version) << 18) >> 26) as u8,
+        (device.version & 0xff) as u8,
+        device.beta_version
+    )
+}
+
+fn to_device(status: &DeviceStatus) -> Device {
+    let (kind, name) = device_kind(status.device_type);
+    Device {
+        port: status.port,
+        kind,
+        version: firmware_version(status),
+        name,
+    }
+}
 
-    let status = connection
+async fn device_statuses(connection: &mut SerialConnection) -> Result<Vec<DeviceStatus>, CliError> {
+    Ok(connection
         .packet_handshake::<GetDeviceStatusReplyPacket>(
             Duration::from_millis(500),
             10,
             GetDeviceStatusPacket::new(()),
         )
         .await?
-        .try_into_inner()?;
+        .try_into_inner()?
+        .devices)
+}
+
+fn print_table(devices: &[DeviceStatus]) {
+    let mut tw = TabWriter::new(io::stdout());
+
     writeln!(
         &mut tw,
         "\x1B[1mPort\tType\tStatus\tFirmware\tBootloader\x1B[0m"
     )
     .unwrap();
 
-    for device in status.devices {
+    for device in devices {
         writeln!(
             &mut tw,
             "{}\t{:?}\t{:#x}\t{}\t{}",
             device.port,
             device.device_type,
             device.status,
-            format_args!(
-                "{}.{}.{}.b{}",
-                (u32::from(device.version) >> 14) as u8,
-                ((u32::from(device.version) << 18) >> 26) as u8,
-                (device.version & 0xff) as u8,
-                device.beta_version
-            ),
+            firmware_version(device),
             format_args!(
                 "{}.{}.{}",
                 (u32::from(device.boot_version) >> 14) as u8,
@@ -50,6 +128,58 @@ pub async fn devices(connection: &mut SerialConnection) -> Result<(), CliError>
     }
 
     tw.flush().unwrap();
+}
+
+/// Prints the devices connected to the brain.
+///
+/// With `json`, each device is printed as a JSON object on its own line. With `watch`, the
+/// devices are polled until cargo-v5 is stopped, and only changes are printed: the table is
+/// reprinted, or a JSON line with an `event` of `"connected"` or `"disconnected"` is printed
+/// for each device that changed.
+pub async fn devices(
+    connection: &mut SerialConnection,
+    json: bool,
+    watch: bool,
+) -> Result<(), CliError> {
+    let mut previous: Vec<Device> = Vec::new();
+
+    loop {
+        let statuses = device_statuses(connection).await?;
+        let devices = statuses.iter().map(to_device).collect::<Vec<_>>();
 
-    Ok(())
+        if !watch {
+            if json {
+                for device in &devices {
+                    println!("{}", serde_json::to_string(device).unwrap());
+                }
+            } else {
+                print_table(&statuses);
+            }
+            return Ok(());
+        }
+
+        if devices != previous {
+            if json {
+                let disconnected = previous.iter().filter(|device| !devices.contains(device));
+                let connected = devices.iter().filter(|device| !previous.contains(device));
+                for (event, device) in disconnected
+                    .map(|device| ("disconnected", device))
+                    .chain(connected.map(|device| ("connected", device)))
+                {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&DeviceEvent { event, device }).unwrap()
+                    );
+                }
+            } else {
+                if !previous.is_empty() {
+                    println!();
+                }
+                print_table(&statuses);
+            }
+            previous = devices;
+        }
+
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
 }
@@ -0,0 +1,185 @@
+use std::path::Path;
+
+use cargo_metadata::camino::Utf8Path;
+use rustc_version::{Channel, VersionMeta};
+use vex_v5_serial::connection::serial::{self, SerialDevice};
+
+use super::build::rustc_cmd;
+use crate::errors::CliError;
+
+/// Outcome of a single `doctor` check.
+enum Status {
+    Pass,
+    /// Something is off, but building and uploading can still work.
+    Warn,
+    /// Building or uploading won't work until this is fixed.
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            message: message.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            message: message.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn print(&self) {
+        let symbol = match self.status {
+            Status::Pass => "\x1b[1;92m✔\x1b[0m",
+            Status::Warn => "\x1b[1;93m!\x1b[0m",
+            Status::Fail => "\x1b[1;91m✘\x1b[0m",
+        };
+        println!("{symbol} {}: {}", self.name, self.message);
+        if let Some(hint) = self.hint {
+            println!("    \x1b[1mhint:\x1b[0m {hint}");
+        }
+    }
+}
+
+fn check_nightly(version: &Result<VersionMeta, rustc_version::Error>) -> Check {
+    const NAME: &str = "Nightly Rust";
+
+    match version {
+        Ok(version) if matches!(version.channel, Channel::Nightly | Channel::Dev) => {
+            Check::pass(NAME, format!("rustc {}", version.semver))
+        }
+        Ok(version) => Check::fail(
+            NAME,
+            format!("rustc {} is not a nightly toolchain", version.semver),
+            "Run `rustup override set nightly` in your project directory.",
+        ),
+        Err(err) => Check::fail(
+            NAME,
+            format!("Could not run rustc ({err})"),
+            "Install Rust from https://rustup.rs, then run `rustup override set nightly`.",
+        ),
+    }
+}
+
+fn check_rust_src(path: &Utf8Path) -> Check {
+    const NAME: &str = "rust-src";
+    const HINT: &str = "Run `rustup component add rust-src` in your project directory.";
+
+    let sysroot = rustc_cmd(path)
+        .args(["--print", "sysroot"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    match sysroot {
+        Some(sysroot)
+            if Path::new(&sysroot)
+                .join("lib/rustlib/src/rust/library/core")
+                .is_dir() =>
+        {
+            Check::pass(NAME, "Installed")
+        }
+        Some(_) => Check::fail(
+            NAME,
+            "Not installed, so the standard library can't be built for the V5",
+            HINT,
+        ),
+        None => Check::fail(NAME, "Could not find the toolchain's sysroot", HINT),
+    }
+}
+
+fn check_devices(devices: &Result<Vec<SerialDevice>, serial::SerialError>) -> Check {
+    const NAME: &str = "V5 devices";
+    const HINT: &str = "Plug in a V5 brain or controller over USB to upload programs.";
+
+    match devices {
+        Ok(devices) if !devices.is_empty() => {
+            Check::pass(NAME, format!("Found {} device(s)", devices.len()))
+        }
+        Ok(_) => Check::warn(NAME, "No devices connected", HINT),
+        Err(err) => Check::warn(NAME, format!("Could not list serial ports ({err})"), HINT),
+    }
+}
+
+/// Checks that the connected devices' serial ports can actually be opened.
+fn check_permissions(devices: &[SerialDevice]) -> Option<Check> {
+    const NAME: &str = "Serial permissions";
+
+    if devices.is_empty() || !cfg!(unix) {
+        return None;
+    }
+
+    let denied = devices
+        .iter()
+        .map(SerialDevice::system_port)
+        .filter(|port| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(port)
+                .is_err_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+        })
+        .collect::<Vec<_>>();
+
+    Some(if denied.is_empty() {
+        Check::pass(NAME, "Serial ports are accessible")
+    } else {
+        Check::fail(
+            NAME,
+            format!("Permission denied for {}", denied.join(", ")),
+            "Add yourself to the `dialout` group with `sudo usermod -aG dialout $USER`, then log out and back in.",
+        )
+    })
+}
+
+/// Checks that everything needed to build and upload vexide projects is set up, printing
+/// a report with hints for anything that isn't.
+pub fn doctor(path: &Utf8Path) -> Result<(), CliError> {
+    let version = VersionMeta::for_command(rustc_cmd(path));
+    let devices = serial::find_devices();
+
+    let mut checks = vec![
+        check_nightly(&version),
+        check_rust_src(path),
+        check_devices(&devices),
+    ];
+    checks.extend(check_permissions(devices.as_deref().unwrap_or_default()));
+
+    for check in &checks {
+        check.print();
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| matches!(check.status, Status::Fail))
+        .count();
+    if failed > 0 {
+        return Err(CliError::DoctorChecksFailed(failed));
+    }
+
+    Ok(())
+}
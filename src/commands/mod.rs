@@ -2,11 +2,18 @@ pub mod build;
 pub mod cat;
 pub mod devices;
 pub mod dir;
+pub mod doctor;
 #[cfg(feature = "field-control")]
 pub mod field_control;
 pub mod log;
 pub mod new;
 pub mod rm;
 pub mod screenshot;
+pub mod size;
+pub mod slots;
+pub mod target;
 pub mod terminal;
+pub mod test;
 pub mod upload;
+pub mod version;
+pub mod watch;
@@ -1,31 +1,339 @@
-use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    semver::{Version, VersionReq},
+};
+use chrono::Datelike;
+use clap::{Args, ValueEnum};
+use inquire::{Text, validator::Validation};
 use log::{debug, info, warn};
+#[cfg(feature = "fetch-template")]
 use serde_json::Value;
+use toml_edit::{Array, DocumentMut, Item, value};
 
+use super::build::{CargoOpts, build, cargo_bin, is_nightly_toolchain, strip_disabled};
 use crate::errors::CliError;
+#[cfg(feature = "fetch-template")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{
-    io,
-    path::{Path, PathBuf},
+    io::{self, IsTerminal},
+    path::{Component, Path, PathBuf},
 };
 
+/// Options for creating a new project.
+#[derive(Args, Debug, Default)]
+pub struct NewOpts {
+    /// Initialize a new repository for the given version control system.
+    #[arg(long, value_enum, default_value_t)]
+    pub vcs: Vcs,
+
+    /// Program slot to configure in the new project's `Cargo.toml`.
+    #[arg(long, value_name = "1-8")]
+    pub slot: Option<u8>,
+
+    /// Author to list in the new project's `Cargo.toml`, formatted as `Name <email>`.
+    #[arg(long, value_name = "NAME <EMAIL>")]
+    pub author: Option<String>,
+
+    /// Warn if the template doesn't depend on a vexide release compatible with this version.
+    #[arg(long, value_name = "VERSION")]
+    pub vexide_version: Option<Version>,
+
+    /// Description to set in the new project's `Cargo.toml`.
+    #[arg(long)]
+    pub description: Option<String>,
+
+    /// Replace the template's README with one about the new project.
+    #[arg(long)]
+    pub readme: bool,
+
+    /// SPDX license expression for the new project, e.g. `MIT OR Apache-2.0`.
+    ///
+    /// The license text is written to `LICENSE` for known licenses (MIT and Apache-2.0).
+    #[arg(long, value_name = "SPDX")]
+    pub license: Option<String>,
+
+    /// Dependencies to add to the new project with `cargo add`, separated by commas.
+    ///
+    /// Without a version, the latest compatible release is added.
+    #[arg(long, value_name = "CRATE[@VERSION]", value_delimiter = ',')]
+    pub deps: Vec<String>,
+
+    /// Add the new project to the members of the workspace at `--path`.
+    #[arg(long)]
+    pub workspace: bool,
+
+    /// Build the new project once it's created, to fetch its dependencies and check that it
+    /// compiles. The project is kept even if the build fails.
+    #[arg(long, overrides_with = "no_verify")]
+    pub build: bool,
+
+    /// Don't build the new project, overriding an earlier `--build`.
+    #[arg(long, overrides_with = "build")]
+    pub no_verify: bool,
+
+    /// Options for the `--build` build, like `cargo v5 build`'s.
+    ///
+    /// Set from environment variables like `CARGO_V5_NO_NIGHTLY_CHECK`.
+    #[arg(skip)]
+    pub cargo_opts: CargoOpts,
+
+    /// Write a `rust-toolchain.toml` pinning nightly Rust if the template doesn't pin it.
+    #[arg(long)]
+    pub pin_toolchain: bool,
+
+    /// Never prompt for input; use defaults instead. Implied when `CI` is set.
+    #[arg(long, conflicts_with = "interactive")]
+    pub non_interactive: bool,
+
+    /// Allow prompting for input, even when `CI` is set.
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Never prompt for input, even with `--interactive`.
+    ///
+    /// Set from the global `--yes` argument.
+    #[arg(skip)]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub download_opts: DownloadOpts,
+}
+
+impl NewOpts {
+    /// Returns `true` if `new` may prompt the user for input.
+    ///
+    /// Like cargo, CI environments are detected through the `CI` environment variable, and
+    /// prompts are also skipped when stdin isn't a terminal.
+    pub fn interactive(&self) -> bool {
+        if self.yes {
+            return false;
+        }
+        if self.interactive {
+            return true;
+        }
+        if self.non_interactive {
+            return false;
+        }
+
+        let ci = std::env::var("CI").is_ok_and(|ci| !matches!(ci.as_str(), "" | "0" | "false"));
+        !ci && std::io::stdin().is_terminal()
+    }
+}
+
+/// Version control system to initialize new projects with.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Vcs {
+    /// Initialize a git repository, if git is installed.
+    #[default]
+    Git,
+    /// Don't initialize any version control.
+    None,
+}
+
+/// Options controlling where `new` gets its project template from.
+#[derive(Args, Debug, Default)]
+pub struct DownloadOpts {
+    /// Do not download the latest template online.
+    #[cfg_attr(
+        feature = "fetch-template",
+        arg(long, visible_alias = "no-download", default_value = "false")
+    )]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip = false))]
+    pub offline: bool,
+
+    /// Download the template archive from this URL instead of vexide-template.
+    #[cfg_attr(feature = "fetch-template", arg(long))]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip))]
+    pub template_url: Option<String>,
+
+    /// Use this tagged release of vexide-template instead of the latest commit.
+    #[cfg_attr(
+        feature = "fetch-template",
+        arg(long, value_name = "TAG", conflicts_with = "template_url")
+    )]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip))]
+    pub template_tag: Option<String>,
+
+    /// Use the latest commit on this branch of vexide-template instead of `main`.
+    #[cfg_attr(
+        feature = "fetch-template",
+        arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = ["template_url", "template_tag"]
+        )
+    )]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip))]
+    pub branch: Option<String>,
+
+    /// How long a cached template is trusted before checking for updates again [default: 3600]
+    #[cfg_attr(feature = "fetch-template", arg(long, value_name = "SECONDS"))]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip))]
+    pub template_cache_ttl: Option<u64>,
+
+    /// How long to wait for the template server to connect or respond before falling back
+    /// to a cached or built-in template [default: 30]
+    #[cfg_attr(feature = "fetch-template", arg(long, value_name = "SECONDS"))]
+    #[cfg_attr(not(feature = "fetch-template"), arg(skip))]
+    pub fetch_timeout: Option<u64>,
+}
+
 #[derive(Debug, Clone)]
 struct Template {
     pub data: Vec<u8>,
+    #[cfg(feature = "fetch-template")]
     pub sha: Option<String>,
+    /// When the template was last confirmed to match the upstream SHA.
+    #[cfg(feature = "fetch-template")]
+    pub checked_at: Option<SystemTime>,
+}
+
+impl Template {
+    /// Returns `true` if this template was checked against upstream less than `ttl` ago.
+    #[cfg(feature = "fetch-template")]
+    fn checked_within(&self, ttl: Duration) -> bool {
+        self.checked_at
+            .and_then(|checked_at| checked_at.elapsed().ok())
+            .is_some_and(|age| age < ttl)
+    }
 }
 
+#[cfg(feature = "fetch-template")]
 const TEMPLATE_FILE_NAME: &str = "vexide-template.tar.gz";
+#[cfg(feature = "fetch-template")]
 const SHA_FILE_NAME: &str = "cache-id.txt";
+#[cfg(feature = "fetch-template")]
+const CHECKED_AT_FILE_NAME: &str = "cache-checked-at.txt";
+#[cfg(feature = "fetch-template")]
+const DEFAULT_TEMPLATE_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+#[cfg(feature = "fetch-template")]
+const TEMPLATE_URL: &str =
+    "https://github.com/vexide/vexide-template/archive/refs/heads/main.tar.gz";
 
 #[cfg(feature = "fetch-template")]
-async fn get_current_sha() -> Result<String, CliError> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://api.github.com/repos/vexide/vexide-template/commits/main?per-page=1")
-        .header("User-Agent", "vexide/cargo-v5")
+const DEFAULT_USER_AGENT: &str = "vexide/cargo-v5";
+#[cfg(feature = "fetch-template")]
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Builds the client used for template requests.
+///
+/// Without a timeout, a stalled connection would make `new` hang instead of falling back
+/// to another template.
+#[cfg(feature = "fetch-template")]
+fn http_client(timeout: Duration) -> Result<reqwest::Client, CliError> {
+    reqwest::Client::builder()
+        .connect_timeout(timeout)
+        .read_timeout(timeout)
+        .build()
+        .map_err(CliError::ReqwestError)
+}
+
+/// The User-Agent sent with template requests, overridable with `CARGO_V5_USER_AGENT`.
+#[cfg(feature = "fetch-template")]
+fn user_agent() -> String {
+    std::env::var("CARGO_V5_USER_AGENT")
+        .ok()
+        .filter(|user_agent| !user_agent.is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string())
+}
+
+/// Builds a request to the GitHub API.
+///
+/// If `GITHUB_TOKEN` is set, it's sent as a bearer token so that shared networks don't run
+/// into GitHub's anonymous rate limit.
+#[cfg(feature = "fetch-template")]
+fn github_api_request(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    let request = client.get(url).header("User-Agent", user_agent());
+
+    match std::env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => request.bearer_auth(token),
+        _ => request,
+    }
+}
+
+/// Returns [`CliError::GitHubRateLimited`] if `response` is GitHub refusing a request
+/// because the rate limit was exhausted.
+#[cfg(feature = "fetch-template")]
+fn rate_limit_error(response: &reqwest::Response) -> Option<CliError> {
+    let status = response.status();
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    let limited = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || (status == reqwest::StatusCode::FORBIDDEN
+            && header("X-RateLimit-Remaining") == Some("0"));
+    if !limited {
+        return None;
+    }
+
+    let retry_after = header("X-RateLimit-Reset")
+        .and_then(|reset| reset.parse::<i64>().ok())
+        .and_then(|reset| chrono::DateTime::from_timestamp(reset, 0))
+        .map(|reset| {
+            format!(
+                "after {}",
+                reset.with_timezone(&chrono::Local).format("%H:%M:%S")
+            )
+        })
+        .unwrap_or_else(|| "later".to_string());
+
+    Some(CliError::GitHubRateLimited { retry_after })
+}
+
+/// The revision of vexide-template to fetch.
+#[cfg(feature = "fetch-template")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateRef<'a> {
+    /// The latest commit on `main`.
+    Main,
+    /// The latest commit on a branch, from `--branch`.
+    Branch(&'a str),
+    /// A tagged release, from `--template-tag`.
+    Tag(&'a str),
+}
+
+/// Returns the URL of the vexide-template archive for `template_ref`.
+#[cfg(feature = "fetch-template")]
+fn template_url(template_ref: TemplateRef) -> String {
+    match template_ref {
+        TemplateRef::Main => TEMPLATE_URL.to_string(),
+        TemplateRef::Branch(branch) => {
+            format!("https://github.com/vexide/vexide-template/archive/refs/heads/{branch}.tar.gz")
+        }
+        TemplateRef::Tag(tag) => {
+            format!("https://github.com/vexide/vexide-template/archive/refs/tags/{tag}.tar.gz")
+        }
+    }
+}
+
+#[cfg(feature = "fetch-template")]
+async fn get_current_sha(
+    client: &reqwest::Client,
+    template_ref: TemplateRef<'_>,
+) -> Result<String, CliError> {
+    let url = match template_ref {
+        TemplateRef::Main => {
+            "https://api.github.com/repos/vexide/vexide-template/commits/main?per-page=1"
+                .to_string()
+        }
+        TemplateRef::Branch(name) | TemplateRef::Tag(name) => {
+            format!("https://api.github.com/repos/vexide/vexide-template/commits/{name}")
+        }
+    };
+    let response = github_api_request(client, &url)
         .send()
         .await
         .map_err(|err| CliError::ReqwestError(err))?;
+
+    if let Some(err) = rate_limit_error(&response) {
+        warn!("{err} Set GITHUB_TOKEN to avoid this.");
+        return Err(err);
+    }
+
     let response_text = response
         .text()
         .await
@@ -36,22 +344,72 @@ async fn get_current_sha() -> Result<String, CliError> {
     }
 }
 
+/// How long to wait before each retry of a failed template download.
+#[cfg(feature = "fetch-template")]
+const DOWNLOAD_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(4),
+];
+
+/// Returns `true` if a download that failed with `err` might succeed if tried again.
+///
+/// Client errors (like a 404 for an unknown tag) won't go away on their own, but
+/// connection problems, timeouts, and server errors often do.
+#[cfg(feature = "fetch-template")]
+fn is_retryable(err: &reqwest::Error) -> bool {
+    match err.status() {
+        Some(status) => status.is_server_error(),
+        None => !err.is_builder() && !err.is_decode(),
+    }
+}
+
 #[cfg(feature = "fetch-template")]
-async fn fetch_template() -> Result<Template, CliError> {
+async fn download_archive(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, CliError> {
+    let mut retry_delays = DOWNLOAD_RETRY_DELAYS.iter();
+
+    loop {
+        let result = async {
+            client
+                .get(url)
+                .header("User-Agent", user_agent())
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(bytes) => return Ok(bytes.to_vec()),
+            Err(err) if is_retryable(&err) => match retry_delays.next() {
+                Some(delay) => {
+                    debug!("Downloading {url} failed ({err}), retrying in {delay:?}...");
+                    tokio::time::sleep(*delay).await;
+                }
+                None => return Err(CliError::ReqwestError(err)),
+            },
+            Err(err) => return Err(CliError::ReqwestError(err)),
+        }
+    }
+}
+
+#[cfg(feature = "fetch-template")]
+async fn fetch_template(
+    client: &reqwest::Client,
+    template_ref: TemplateRef<'_>,
+) -> Result<Template, CliError> {
     debug!("Fetching template...");
-    let response =
-        reqwest::get("https://github.com/vexide/vexide-template/archive/refs/heads/main.tar.gz")
-            .await;
-    let response = match response {
-        Ok(response) => response,
-        Err(err) => return Err(CliError::ReqwestError(err)),
-    };
-    let bytes = response.bytes().await?;
+    let data = download_archive(client, &template_url(template_ref)).await?;
 
     debug!("Successfully fetched template.");
     let template = Template {
-        data: bytes.to_vec(),
-        sha: get_current_sha().await.ok(),
+        data,
+        sha: get_current_sha(client, template_ref).await.ok(),
+        // A tagged release or other branch isn't what `main` resolves to, so it must not be
+        // trusted by the update check TTL.
+        checked_at: (template_ref == TemplateRef::Main).then(SystemTime::now),
     };
     store_cached_template(template.clone()).await;
     Ok(template)
@@ -61,28 +419,92 @@ async fn fetch_template() -> Result<Template, CliError> {
 async fn get_cached_template() -> Option<Template> {
     match cached_template_dir() {
         Some(dir) => {
-            let cache_file = dir.with_file_name(TEMPLATE_FILE_NAME);
-            let sha_file = dir.with_file_name(SHA_FILE_NAME);
+            let cache_file = dir.join(TEMPLATE_FILE_NAME);
+            let sha_file = dir.join(SHA_FILE_NAME);
+            let checked_at_file = dir.join(CHECKED_AT_FILE_NAME);
             let sha = tokio::fs::read_to_string(sha_file).await.ok();
+            let checked_at = tokio::fs::read_to_string(checked_at_file)
+                .await
+                .ok()
+                .and_then(|secs| secs.trim().parse().ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
             let data = tokio::fs::read(cache_file).await.ok();
-            data.map(|data| Template { data, sha })
+            data.map(|data| Template {
+                data,
+                sha,
+                checked_at,
+            })
         }
         None => None,
     }
 }
 
 #[cfg(feature = "fetch-template")]
-async fn store_cached_template(template: Template) -> () {
+async fn store_cached_template(template: Template) {
+    let Some(dir) = cached_template_dir() else {
+        return;
+    };
+    if let Err(err) = tokio::fs::create_dir_all(&dir).await {
+        debug!("Could not create the template cache directory: {err}");
+        return;
+    }
+
+    // The SHA and check time describe the archive, so they're only written once the
+    // archive itself has been stored. Otherwise they'd vouch for a stale or missing one.
+    if let Err(err) = write_atomic(&dir.join(TEMPLATE_FILE_NAME), &template.data).await {
+        debug!("Could not cache the template: {err}");
+        return;
+    }
+
+    let sha_file = dir.join(SHA_FILE_NAME);
+    let sha_written = match template.sha {
+        Some(sha) => write_atomic(&sha_file, sha).await.is_ok(),
+        None => false,
+    };
+    if !sha_written {
+        let _ = tokio::fs::remove_file(sha_file).await;
+    }
+
+    if template.checked_at.is_some() {
+        mark_cached_template_checked().await;
+    } else {
+        let _ = tokio::fs::remove_file(dir.join(CHECKED_AT_FILE_NAME)).await;
+    }
+}
+
+/// Records that the cached template was just confirmed to be up to date.
+#[cfg(feature = "fetch-template")]
+async fn mark_cached_template_checked() {
     if let Some(dir) = cached_template_dir() {
-        let cache_file = dir.with_file_name(TEMPLATE_FILE_NAME);
-        let sha_file = dir.with_file_name(SHA_FILE_NAME);
-        let _ = tokio::fs::write(cache_file, &template.data).await;
-        if let Some(sha) = template.sha {
-            let _ = tokio::fs::write(sha_file, sha).await;
-        }
+        let checked_at_file = dir.join(CHECKED_AT_FILE_NAME);
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let _ = write_atomic(&checked_at_file, secs.to_string()).await;
     }
 }
 
+/// Writes `contents` to a temporary file next to `path`, then renames it into place.
+///
+/// Renaming is atomic, so if writing is interrupted, `path` still holds its old contents
+/// rather than a truncated file.
+#[cfg(feature = "fetch-template")]
+async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = match tokio::fs::write(&temp_path, contents).await {
+        Ok(()) => tokio::fs::rename(&temp_path, path).await,
+        Err(err) => Err(err),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
 #[cfg(feature = "fetch-template")]
 fn cached_template_dir() -> Option<PathBuf> {
     use directories::ProjectDirs;
@@ -92,7 +514,10 @@ fn cached_template_dir() -> Option<PathBuf> {
 fn baked_in_template() -> Template {
     Template {
         data: include_bytes!("./vexide-template.tar.gz").to_vec(),
+        #[cfg(feature = "fetch-template")]
         sha: None,
+        #[cfg(feature = "fetch-template")]
+        checked_at: None,
     }
 }
 
@@ -103,7 +528,27 @@ fn unpack_template(template: Vec<u8>, dir: &Utf8PathBuf) -> io::Result<()> {
         let mut entry = entry?;
 
         let path = entry.path()?;
-        let stripped_path = path.iter().skip(1).collect::<PathBuf>();
+        // Archives made with `tar -C dir .` start every entry with `./`.
+        let components = path
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect::<Vec<_>>();
+
+        // Skip the `__MACOSX` metadata directory, AppleDouble `._*` files, and `.DS_Store`
+        // files that macOS adds to archives, which would otherwise be unpacked over the
+        // template's files.
+        let is_macos_metadata = components
+            .iter()
+            .any(|component| component.as_os_str() == "__MACOSX")
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("._") || name == ".DS_Store");
+        if is_macos_metadata {
+            continue;
+        }
+
+        let stripped_path = components.iter().skip(1).collect::<PathBuf>();
 
         if let Some(stripped_path) = stripped_path.to_str() {
             let output_path = Path::new(dir).join(stripped_path);
@@ -118,14 +563,393 @@ fn unpack_template(template: Vec<u8>, dir: &Utf8PathBuf) -> io::Result<()> {
     Ok(())
 }
 
-pub async fn new(
-    path: Utf8PathBuf,
-    name: Option<String>,
-    download_template: bool,
+/// Turns a failure to create files in the project directory `dir` into a [`CliError`],
+/// pointing out `--path` if the location can't be written to.
+fn project_dir_error(err: io::Error, dir: &Utf8Path) -> CliError {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied
+        | io::ErrorKind::ReadOnlyFilesystem
+        | io::ErrorKind::NotADirectory
+        | io::ErrorKind::NotFound => CliError::ProjectDirNotCreatable {
+            path: dir.to_owned(),
+            source: err,
+        },
+        _ => CliError::IoError(err),
+    }
+}
+
+/// Sets `key` to `value` in the manifest's `[table]`, a dotted path like
+/// `package.metadata.v5`. Tables that don't exist yet are created.
+fn set_manifest_value(
+    manifest: &mut DocumentMut,
+    table: &str,
+    key: &str,
+    value: impl Into<toml_edit::Value>,
+) {
+    let mut item = manifest.as_item_mut();
+    for name in table.split('.') {
+        let next = &mut item[name];
+        if next.is_none() {
+            // Parent tables like `[package.metadata]` aren't written out on their own.
+            let mut new_table = toml_edit::Table::new();
+            new_table.set_implicit(true);
+            *next = Item::Table(new_table);
+        }
+        item = next;
+    }
+    item[key] = toml_edit::value(value);
+}
+
+/// Returns the version requirement of `name` in the manifest's `[dependencies]` table.
+fn manifest_dependency_version(manifest: &DocumentMut, name: &str) -> Option<String> {
+    let dependency = manifest.get("dependencies")?.get(name)?;
+
+    // Either `name = "1.0"`, or a table with a `version` key like `name = { version = "1.0" }`
+    // or `[dependencies.name]`. Workspace dependencies (`name.workspace = true`) have neither.
+    dependency
+        .as_str()
+        .or_else(|| dependency.get("version")?.as_str())
+        .map(str::to_string)
+}
+
+/// Warns if the template's vexide dependency isn't compatible with the requested version.
+fn check_vexide_version(manifest: &DocumentMut, version: &Version) {
+    let Some(requirement) = manifest_dependency_version(manifest, "vexide") else {
+        warn!("The template doesn't depend on a specific vexide version.");
+        return;
+    };
+
+    match VersionReq::parse(&requirement) {
+        Ok(req) if req.matches(version) => {
+            debug!("Template's vexide requirement {req} matches {version}.");
+        }
+        Ok(_) | Err(_) => warn!(
+            "The template depends on vexide {requirement}, which isn't compatible with {version}. \
+             Try a `--template-tag` targeting vexide {version}, or update the `vexide` \
+             dependency in Cargo.toml."
+        ),
+    }
+}
+
+/// License texts that `new --license` can write, along with the suffix used for their file
+/// when a project has more than one license (e.g. `LICENSE-APACHE`).
+const LICENSES: &[(&str, &str, &str)] = &[
+    ("MIT", "MIT", include_str!("./licenses/MIT.txt")),
+    (
+        "Apache-2.0",
+        "APACHE",
+        include_str!("./licenses/Apache-2.0.txt"),
+    ),
+];
+
+/// Writes the text of each license in the SPDX `expression` to the project.
+///
+/// A single license is written to `LICENSE`, while expressions like `MIT OR Apache-2.0` get
+/// a `LICENSE-<NAME>` file per license, as is common for Rust projects.
+fn write_license_files(dir: &Utf8Path, expression: &str, holder: &str) -> Result<(), CliError> {
+    let ids = expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|id| !id.is_empty() && !matches!(*id, "OR" | "AND" | "WITH"))
+        .collect::<Vec<_>>();
+
+    let mut known = Vec::new();
+    for id in &ids {
+        match LICENSES.iter().find(|(spdx, ..)| spdx == id) {
+            Some(license) => known.push(license),
+            None => warn!("No license text is known for `{id}`, so it won't be written."),
+        }
+    }
+
+    let year = chrono::Local::now().year().to_string();
+    for (_, suffix, text) in &known {
+        let file_name = if ids.len() == 1 {
+            "LICENSE".to_string()
+        } else {
+            format!("LICENSE-{suffix}")
+        };
+        let text = text.replace("[year]", &year).replace("[fullname]", holder);
+        std::fs::write(dir.join(file_name), text)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that `author` looks like cargo's `Name <email>` author format.
+fn is_valid_author(author: &str) -> bool {
+    let Some((name, email)) = author
+        .strip_suffix('>')
+        .and_then(|author| author.split_once(" <"))
+    else {
+        return false;
+    };
+    let Some((user, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    let forbidden = ['<', '>', '@', ' '];
+    !name.trim().is_empty()
+        && !name.contains(['<', '>'])
+        && !user.is_empty()
+        && !domain.is_empty()
+        && !user.contains(forbidden)
+        && !domain.contains(forbidden)
+}
+
+/// Adds `deps` (each a `crate[@version]` spec) to the manifest at `manifest_path` with
+/// `cargo add`.
+fn add_dependencies(
+    manifest_path: &Utf8Path,
+    deps: &[String],
+    offline: bool,
 ) -> Result<(), CliError> {
+    debug!("Adding dependencies: {}", deps.join(", "));
+    let mut cargo_add = std::process::Command::new(cargo_bin());
+    cargo_add
+        .arg("add")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .args(deps);
+    if offline {
+        cargo_add.arg("--offline");
+    }
+
+    if !cargo_add.status()?.success() {
+        return Err(CliError::AddDependenciesFailed);
+    }
+
+    Ok(())
+}
+
+/// Reads the manifest of the workspace rooted at `path`.
+fn read_workspace_manifest(path: &Utf8Path) -> Result<(Utf8PathBuf, DocumentMut), CliError> {
+    let manifest_path = path.join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .ok()
+        .map(|manifest| manifest.parse::<DocumentMut>())
+        .transpose()?
+        .filter(|manifest| manifest.get("workspace").is_some_and(Item::is_table_like))
+        .ok_or_else(|| CliError::WorkspaceNotFound(manifest_path.clone()))?;
+
+    Ok((manifest_path, manifest))
+}
+
+/// Adds `member` to the `workspace.members` array of a workspace manifest.
+fn add_workspace_member(
+    manifest_path: &Utf8Path,
+    mut manifest: DocumentMut,
+    member: &str,
+) -> Result<(), CliError> {
+    let member = member.replace('\\', "/");
+    let members = manifest["workspace"]["members"].or_insert(value(Array::new()));
+    if let Some(members) = members.as_array_mut()
+        && !members
+            .iter()
+            .any(|existing| existing.as_str() == Some(&member))
+    {
+        members.push(member);
+    }
+
+    std::fs::write(manifest_path, manifest.to_string())?;
+    Ok(())
+}
+
+/// A README for a new project, replacing the template's README (which is about the template
+/// itself).
+fn project_readme(name: &str, description: Option<&str>) -> String {
+    let mut readme = format!("# {name}\n\n");
+    if let Some(description) = description {
+        readme.push_str(&format!("{description}\n\n"));
+    }
+    readme.push_str(
+        "## Building
+
+This is a [vexide](https://vexide.dev) project. Build it with [cargo-v5](https://github.com/vexide/cargo-v5):
+
+```console
+cargo v5 build
+```
+
+Then, connect to the brain or controller over USB and upload the program:
+
+```console
+cargo v5 upload
+```
+
+## Learn
+
+See the [vexide documentation](https://vexide.dev/docs/) for guides, and the \
+[API reference](https://docs.rs/vexide) on docs.rs.
+",
+    );
+    readme
+}
+
+/// Tells the user how to build and upload the project they just created, like `cargo new`.
+fn print_next_steps(name: &str, dir: &Utf8Path) {
+    println!("     \x1b[1;92mCreated\x1b[0m `{name}` at {dir}");
+    println!();
+    println!("Next steps:");
+    let dir = dir.strip_prefix(".").unwrap_or(dir);
+    if !dir.as_str().is_empty() {
+        if dir.as_str().contains(char::is_whitespace) {
+            println!("    cd \"{dir}\"");
+        } else {
+            println!("    cd {dir}");
+        }
+    }
+    println!("    cargo v5 build");
+    println!("    cargo v5 upload");
+}
+
+/// Makes sure the project in `dir` will be built with nightly Rust.
+///
+/// If its `rust-toolchain.toml` doesn't pin a nightly toolchain, one is pinned with
+/// `pin_toolchain`. Otherwise, a warning is printed if the toolchain that would be used
+/// isn't nightly.
+fn check_toolchain(dir: &Utf8Path, pin_toolchain: bool) -> Result<(), CliError> {
+    let toolchain_path = dir.join("rust-toolchain.toml");
+    let mut toolchain = match std::fs::read_to_string(&toolchain_path) {
+        Ok(toolchain) => toolchain.parse::<DocumentMut>()?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let channel = toolchain
+        .get("toolchain")
+        .and_then(|toolchain| toolchain.get("channel"))
+        .and_then(Item::as_str);
+    if channel.is_some_and(|channel| channel.starts_with("nightly")) {
+        return Ok(());
+    }
+
+    if pin_toolchain {
+        debug!("Pinning the nightly toolchain...");
+        let table = toolchain["toolchain"].or_insert(toml_edit::table());
+        table["channel"] = value("nightly");
+        // `-Zbuild-std` builds the standard library from source.
+        if table.get("components").is_none() {
+            table["components"] = value(Array::from_iter(["rust-src"]));
+        }
+        std::fs::write(&toolchain_path, toolchain.to_string())?;
+    } else if !is_nightly_toolchain(dir) {
+        warn!(
+            "vexide projects need nightly Rust, but this project's toolchain isn't nightly. \
+             Run `rustup override set nightly` in the project, or pass `--pin-toolchain`."
+        );
+    }
+
+    Ok(())
+}
+
+const DEFAULT_GITIGNORE: &str = "/target\n*.bin\n*.stripped\n";
+
+/// Initializes a git repository in `dir`, unless git isn't installed or `dir` is already
+/// inside a repository.
+fn init_git_repo(dir: &Utf8PathBuf) -> io::Result<()> {
+    let inside_work_tree = std::process::Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .current_dir(dir)
+        .output();
+
+    match inside_work_tree {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            debug!("git is not installed, skipping repository initialization.");
+            return Ok(());
+        }
+        Ok(output) if output.status.success() => {
+            debug!("Project is already inside a git repository, skipping initialization.");
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    debug!("Initializing git repository...");
+    let output = std::process::Command::new("git")
+        .arg("init")
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        warn!(
+            "Could not initialize git repository: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Ok(());
+    }
+
+    let gitignore_path = dir.join(".gitignore");
+    if !gitignore_path.exists() {
+        std::fs::write(gitignore_path, DEFAULT_GITIGNORE)?;
+    }
+
+    Ok(())
+}
+
+/// The name for `cargo v5 new`'s project, prompting for one if it wasn't given and `opts`
+/// allows prompts.
+pub fn project_name(name: Option<String>, opts: &NewOpts) -> Result<String, CliError> {
+    if let Some(name) = name {
+        return Ok(name);
+    }
+    if !opts.interactive() {
+        return Err(CliError::NoProjectName);
+    }
+
+    Text::new("Project name:")
+        .with_validator(|name: &str| {
+            Ok(if name.trim().is_empty() {
+                Validation::Invalid("The name can't be empty".into())
+            } else {
+                Validation::Valid
+            })
+        })
+        .prompt()
+        .map(|name| name.trim().to_string())
+        .map_err(|_| CliError::NoProjectName)
+}
+
+pub async fn new(path: Utf8PathBuf, name: Option<String>, opts: NewOpts) -> Result<(), CliError> {
+    let NewOpts {
+        vcs,
+        slot,
+        author,
+        vexide_version,
+        description,
+        readme,
+        license,
+        deps,
+        workspace,
+        build: build_project,
+        mut cargo_opts,
+        pin_toolchain,
+        download_opts,
+        ..
+    } = opts;
+    let offline = download_opts.offline;
+
+    if let Some(slot) = slot
+        && !(1..=8).contains(&slot)
+    {
+        return Err(CliError::InvalidSlot(slot));
+    }
+    if let Some(author) = &author
+        && !is_valid_author(author)
+    {
+        return Err(CliError::InvalidAuthor(author.clone()));
+    }
+
+    // The workspace is checked up front so a project isn't created only to fail afterwards.
+    // Its manifest can't be overwritten by the template, since the project directory has to
+    // be empty.
+    let workspace = if workspace {
+        Some(read_workspace_manifest(&path)?)
+    } else {
+        None
+    };
+    let member = name.clone();
+
     let dir = if let Some(name) = &name {
         let dir = path.join(name);
-        std::fs::create_dir_all(&path).unwrap();
+        std::fs::create_dir_all(&path).map_err(|err| project_dir_error(err, &path))?;
         dir
     } else {
         path
@@ -148,22 +972,84 @@ pub async fn new(
         .unwrap_or("vexide project".to_string());
 
     #[cfg(feature = "fetch-template")]
-    let template = match (get_cached_template().await, get_current_sha().await) {
-        (cached_template, ..) if !download_template => cached_template,
-        (Some(cached_template), Ok(current_sha))
-            if cached_template.sha == Some(current_sha.clone()) =>
-        {
-            debug!("Cached template is current, skipping download.");
-            Some(cached_template)
-        }
-        (cached_template, ..) => {
-            debug!("Cached template is out of date.");
-            let fetched_template = fetch_template().await.ok();
-            fetched_template.or_else(|| {
-                warn!("Could not fetch template, falling back to cache.");
-                cached_template
+    let download_template = !download_opts.offline;
+    #[cfg(feature = "fetch-template")]
+    let cache_ttl = download_opts
+        .template_cache_ttl
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TEMPLATE_CACHE_TTL);
+    #[cfg(feature = "fetch-template")]
+    let template_ref = match (&download_opts.template_tag, &download_opts.branch) {
+        (Some(tag), _) => TemplateRef::Tag(tag),
+        (None, Some(branch)) => TemplateRef::Branch(branch),
+        (None, None) => TemplateRef::Main,
+    };
+    #[cfg(feature = "fetch-template")]
+    if template_ref != TemplateRef::Main && !download_template {
+        warn!(
+            "`--template-tag` and `--branch` can't be used offline; the cached template will be used instead."
+        );
+    }
+
+    #[cfg(feature = "fetch-template")]
+    let client = http_client(
+        download_opts
+            .fetch_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_FETCH_TIMEOUT),
+    )?;
+    #[cfg(feature = "fetch-template")]
+    let template = match download_opts.template_url {
+        // Custom templates aren't tracked by the cache, since their SHA can't be checked
+        // against vexide-template's.
+        Some(url) if download_template => {
+            debug!("Fetching custom template from {url}...");
+            // Like a tag or branch, a custom template that can't be downloaded isn't
+            // replaced by a different one.
+            Some(Template {
+                data: download_archive(&client, &url).await?,
+                sha: None,
+                checked_at: None,
             })
         }
+        _ => match get_cached_template().await {
+            cached_template if !download_template => cached_template,
+            Some(cached_template)
+                if template_ref == TemplateRef::Main
+                    && cached_template.checked_within(cache_ttl) =>
+            {
+                debug!("Cached template was checked recently, skipping update check.");
+                Some(cached_template)
+            }
+            cached_template => match get_current_sha(&client, template_ref).await {
+                Ok(current_sha)
+                    if cached_template
+                        .as_ref()
+                        .is_some_and(|cached| cached.sha.as_ref() == Some(&current_sha)) =>
+                {
+                    debug!("Cached template is current, skipping download.");
+                    if template_ref == TemplateRef::Main {
+                        mark_cached_template_checked().await;
+                    }
+                    cached_template
+                }
+                _ => {
+                    debug!("Cached template is out of date.");
+                    match fetch_template(&client, template_ref).await {
+                        Ok(fetched_template) => Some(fetched_template),
+                        // The cache and built-in template are `main`, not the requested tag or
+                        // branch.
+                        Err(err) if template_ref != TemplateRef::Main => {
+                            return Err(err);
+                        }
+                        Err(_) => {
+                            warn!("Could not fetch template, falling back to cache.");
+                            cached_template
+                        }
+                    }
+                }
+            },
+        },
     }
     .unwrap_or_else(|| {
         debug!("No template found in cache, using builtin template.");
@@ -171,18 +1057,95 @@ pub async fn new(
     });
 
     #[cfg(not(feature = "fetch-template"))]
-    let template = baked_in_template();
+    let template = {
+        _ = download_opts;
+        baked_in_template()
+    };
 
     debug!("Unpacking template...");
-    unpack_template(template.data, &dir)?;
+    unpack_template(template.data, &dir).map_err(|err| project_dir_error(err, &dir))?;
     debug!("Successfully unpacked vexide-template!");
 
     debug!("Renaming project to {}...", &name);
     let manifest_path = dir.join("Cargo.toml");
     let manifest = tokio::fs::read_to_string(&manifest_path).await?;
-    let manifest = manifest.replace("vexide-template", &name);
-    tokio::fs::write(manifest_path, manifest).await?;
+    let mut manifest = manifest
+        .replace("vexide-template", &name)
+        .parse::<DocumentMut>()?;
+    if let Some(version) = &vexide_version {
+        check_vexide_version(&manifest, version);
+    }
+    if let Some(author) = &author {
+        debug!("Setting author to {author}...");
+        set_manifest_value(
+            &mut manifest,
+            "package",
+            "authors",
+            Array::from_iter([author.as_str()]),
+        );
+    }
+    if let Some(description) = &description {
+        debug!("Setting description to {description}...");
+        set_manifest_value(
+            &mut manifest,
+            "package",
+            "description",
+            description.as_str(),
+        );
+    }
+    if readme {
+        debug!("Writing README...");
+        tokio::fs::write(
+            dir.join("README.md"),
+            project_readme(&name, description.as_deref()),
+        )
+        .await?;
+    }
+    if let Some(license) = &license {
+        debug!("Setting license to {license}...");
+        let holder = author
+            .as_deref()
+            .and_then(|author| author.split_once(" <"))
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| format!("The {name} Authors"));
+        write_license_files(&dir, license, &holder)?;
+        set_manifest_value(&mut manifest, "package", "license", license.as_str());
+    }
+    if let Some(slot) = slot {
+        debug!("Setting program slot to {slot}...");
+        set_manifest_value(
+            &mut manifest,
+            "package.metadata.v5",
+            "slot",
+            i64::from(slot),
+        );
+    }
+    tokio::fs::write(&manifest_path, manifest.to_string()).await?;
+
+    if !deps.is_empty() {
+        add_dependencies(&manifest_path, &deps, offline)?;
+    }
+
+    if let (Some((workspace_manifest_path, workspace_manifest)), Some(member)) = (workspace, member)
+    {
+        debug!("Adding {member} to the workspace's members...");
+        add_workspace_member(&workspace_manifest_path, workspace_manifest, &member)?;
+    }
+
+    check_toolchain(&dir, pin_toolchain)?;
+
+    if vcs == Vcs::Git {
+        init_git_repo(&dir)?;
+    }
 
     info!("Successfully created new project at {:?}", dir);
+    print_next_steps(&name, &dir);
+
+    if build_project {
+        debug!("Building the new project...");
+        cargo_opts.no_strip |= strip_disabled(&cargo_opts.manifest_path(&dir))?;
+        build(&dir, cargo_opts).await?;
+    }
+
     Ok(())
 }
@@ -1,5 +1,7 @@
 use std::{path::PathBuf, str::FromStr, time::Duration};
 
+use inquire::Confirm;
+use log::warn;
 use vex_v5_serial::{
     connection::{
         serial::{SerialConnection, SerialError},
@@ -7,25 +9,20 @@ use vex_v5_serial::{
     },
     packets::file::{
         EraseFilePacket, EraseFilePayload, EraseFileReplyPacket, ExitFileTransferPacket,
-        ExitFileTransferReplyPacket, FileExitAction,
+        ExitFileTransferReplyPacket, FileExitAction, FileVendor,
     },
     string::FixedString,
 };
 
 use crate::errors::CliError;
 
-use super::cat::vendor_from_prefix;
-
-pub async fn rm(connection: &mut SerialConnection, file: PathBuf) -> Result<(), CliError> {
-    let vendor = vendor_from_prefix(if let Some(parent) = file.parent() {
-        parent.to_str().unwrap()
-    } else {
-        ""
-    });
-
-    let file_name = FixedString::from_str(file.file_name().unwrap_or_default().to_str().unwrap())
-        .map_err(|err| CliError::SerialError(SerialError::EncodeError(err)))?;
+use super::{cat::vendor_from_prefix, upload::brain_file_metadata};
 
+async fn erase_file(
+    connection: &mut SerialConnection,
+    vendor: FileVendor,
+    file_name: FixedString<23>,
+) -> Result<(), CliError> {
     connection
         .packet_handshake::<EraseFileReplyPacket>(
             Duration::from_millis(500),
@@ -50,3 +47,68 @@ pub async fn rm(connection: &mut SerialConnection, file: PathBuf) -> Result<(),
 
     Ok(())
 }
+
+pub async fn rm(connection: &mut SerialConnection, file: PathBuf) -> Result<(), CliError> {
+    let vendor = vendor_from_prefix(if let Some(parent) = file.parent() {
+        parent.to_str().unwrap()
+    } else {
+        ""
+    });
+
+    let file_name = FixedString::from_str(file.file_name().unwrap_or_default().to_str().unwrap())
+        .map_err(|err| CliError::SerialError(SerialError::EncodeError(err)))?;
+
+    erase_file(connection, vendor, file_name).await
+}
+
+/// Erases the program in `slot`, asking for confirmation first unless `force` is set.
+///
+/// An empty slot isn't an error, since there's nothing left to erase.
+pub async fn rm_slot(
+    connection: &mut SerialConnection,
+    slot: u8,
+    force: bool,
+) -> Result<(), CliError> {
+    if !(1..=8).contains(&slot) {
+        return Err(CliError::SlotOutOfRange);
+    }
+
+    // A program is its binary and the `.ini` describing it, plus the base binary that
+    // differential uploads patch.
+    let mut files = Vec::new();
+    for file_name in [
+        format!("slot_{slot}.bin"),
+        format!("slot_{slot}.ini"),
+        format!("slot_{slot}.base.bin"),
+    ] {
+        let file_name = FixedString::new(file_name).unwrap();
+        if brain_file_metadata(connection, file_name.clone(), FileVendor::User)
+            .await?
+            .is_some()
+        {
+            files.push(file_name);
+        }
+    }
+
+    if files.is_empty() {
+        warn!("Slot {slot} is already empty.");
+        return Ok(());
+    }
+
+    if !force {
+        let confirmed = Confirm::new(&format!("Erase the program in slot {slot}?"))
+            .with_default(false)
+            .prompt()
+            .map_err(|_| CliError::EraseNotConfirmed)?;
+        if !confirmed {
+            return Ok(());
+        }
+    }
+
+    for file_name in files {
+        erase_file(connection, FileVendor::User, file_name).await?;
+    }
+    println!("      \x1b[1;92mErased\x1b[0m slot {slot}");
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+
+use cargo_metadata::camino::Utf8Path;
+use clap::{Args, ValueEnum};
+use humansize::{BINARY, format_size};
+use object::{Object, ObjectSection, ObjectSymbol, SectionFlags, SymbolKind, elf::SHF_ALLOC};
+use serde::Serialize;
+use tabwriter::TabWriter;
+
+use crate::errors::CliError;
+
+/// Options for `cargo v5 build --analyze-size`.
+#[derive(Args, Debug, Clone)]
+pub struct SizeOpts {
+    /// Print the largest sections and symbols of each program after building it.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub analyze_size: bool,
+
+    /// Print the size report as JSON, with one object per line for each program.
+    #[arg(long, requires = "analyze_size")]
+    pub json: bool,
+
+    /// How to sort the size report.
+    #[arg(long, value_enum, default_value_t, requires = "analyze_size")]
+    pub sort: SizeSort,
+
+    /// How many of the largest symbols to include in the size report (0 for all).
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        requires = "analyze_size"
+    )]
+    pub top: usize,
+}
+
+/// Order of the entries in a size report.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeSort {
+    /// Largest first.
+    #[default]
+    Size,
+    /// Alphabetically by name.
+    Name,
+    /// By address in memory.
+    Address,
+}
+
+#[derive(Serialize)]
+struct SizeEntry {
+    name: String,
+    address: u64,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct SizeReport<'a> {
+    elf: &'a str,
+    sections: Vec<SizeEntry>,
+    symbols: Vec<SizeEntry>,
+}
+
+fn sort_entries(entries: &mut [SizeEntry], sort: SizeSort) {
+    match sort {
+        SizeSort::Size => entries.sort_by(|a, b| b.size.cmp(&a.size).then(a.name.cmp(&b.name))),
+        SizeSort::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        SizeSort::Address => entries.sort_by_key(|entry| entry.address),
+    }
+}
+
+fn print_table(
+    tw: &mut TabWriter<io::Stdout>,
+    kind: &str,
+    entries: &[SizeEntry],
+) -> io::Result<()> {
+    writeln!(tw, "\x1B[1m{kind}\tAddress\tSize\x1B[0m")?;
+    for entry in entries {
+        writeln!(
+            tw,
+            "{}\t{:#010x}\t{}",
+            entry.name,
+            entry.address,
+            format_size(entry.size, BINARY)
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints a breakdown of what takes up space in the ELF at `elf_path`.
+///
+/// Only allocated sections are included, since those are the ones that end up in the
+/// program binary or in memory on the brain.
+pub fn analyze_size(elf_path: &Utf8Path, opts: &SizeOpts) -> Result<(), CliError> {
+    let data = std::fs::read(elf_path)?;
+    let elf = object::File::parse(&*data)?;
+
+    let mut sections = elf
+        .sections()
+        .filter(|section| {
+            section.size() > 0
+                && matches!(
+                    section.flags(),
+                    SectionFlags::Elf { sh_flags } if sh_flags & u64::from(SHF_ALLOC) != 0
+                )
+        })
+        .map(|section| SizeEntry {
+            name: section.name().unwrap_or("<unknown>").to_string(),
+            address: section.address(),
+            size: section.size(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut symbols = elf
+        .symbols()
+        .filter(|symbol| {
+            symbol.size() > 0 && matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)
+        })
+        .map(|symbol| SizeEntry {
+            name: format!(
+                "{:#}",
+                rustc_demangle::demangle(symbol.name().unwrap_or("<unknown>"))
+            ),
+            address: symbol.address(),
+            size: symbol.size(),
+        })
+        .collect::<Vec<_>>();
+
+    // `--top` always keeps the largest symbols, regardless of how they're sorted.
+    if opts.top > 0 {
+        sort_entries(&mut symbols, SizeSort::Size);
+        symbols.truncate(opts.top);
+    }
+    sort_entries(&mut sections, opts.sort);
+    sort_entries(&mut symbols, opts.sort);
+
+    if opts.json {
+        let report = SizeReport {
+            elf: elf_path.as_str(),
+            sections,
+            symbols,
+        };
+        println!("{}", serde_json::to_string(&report).unwrap());
+        return Ok(());
+    }
+
+    let total = sections.iter().map(|section| section.size).sum::<u64>();
+    println!(
+        "\x1B[1mSize of {elf_path}\x1B[0m ({} allocated)\n",
+        format_size(total, BINARY)
+    );
+
+    let mut tw = TabWriter::new(io::stdout());
+    print_table(&mut tw, "Section", &sections)?;
+    writeln!(tw)?;
+    print_table(&mut tw, "Symbol", &symbols)?;
+    tw.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::io::{self, Write};
+
+use chrono::{TimeZone, Utc};
+use humansize::{BINARY, format_size};
+use tabwriter::TabWriter;
+use vex_v5_serial::{
+    commands::file::{DownloadFile, ProgramIniConfig},
+    connection::{Connection, serial::SerialConnection},
+    packets::file::{FileTransferTarget, FileVendor},
+    string::FixedString,
+    timestamp::J2000_EPOCH,
+};
+
+use super::upload::brain_file_metadata;
+use crate::errors::CliError;
+
+/// Reads the program name from a slot's `.ini` file, if it has one.
+async fn program_name(connection: &mut SerialConnection, slot: u8) -> Option<String> {
+    let file_name = FixedString::new(format!("slot_{slot}.ini")).unwrap();
+    let metadata = brain_file_metadata(connection, file_name.clone(), FileVendor::User)
+        .await
+        .ok()??;
+
+    let ini = connection
+        .execute_command(DownloadFile {
+            file_name,
+            size: metadata.size,
+            vendor: FileVendor::User,
+            target: Some(FileTransferTarget::Qspi),
+            load_addr: metadata.load_address,
+            progress_callback: None,
+        })
+        .await
+        .ok()?;
+
+    serde_ini::from_read::<_, ProgramIniConfig>(&ini[..])
+        .ok()
+        .map(|config| config.program.name)
+}
+
+/// Prints the program installed in each of the brain's 8 slots, so they can be checked
+/// before being overwritten.
+pub async fn slots(connection: &mut SerialConnection) -> Result<(), CliError> {
+    let mut tw = TabWriter::new(io::stdout());
+    writeln!(&mut tw, "\x1B[1mSlot\tName\tSize\tUploaded\x1B[0m").unwrap();
+
+    for slot in 1..=8 {
+        let file_name = FixedString::new(format!("slot_{slot}.bin")).unwrap();
+        let Some(binary) = brain_file_metadata(connection, file_name, FileVendor::User).await?
+        else {
+            writeln!(&mut tw, "{slot}\tempty\t-\t-").unwrap();
+            continue;
+        };
+
+        let name = program_name(connection, slot)
+            .await
+            .unwrap_or_else(|| "-".to_string());
+        let uploaded = Utc
+            .timestamp_opt(J2000_EPOCH as i64 + binary.metadata.timestamp as i64, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        writeln!(
+            &mut tw,
+            "{slot}\t{name}\t{}\t{uploaded}",
+            format_size(binary.size, BINARY)
+        )
+        .unwrap();
+    }
+
+    tw.flush().unwrap();
+    Ok(())
+}
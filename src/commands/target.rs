@@ -0,0 +1,82 @@
+use cargo_metadata::camino::Utf8Path;
+use fs_err::tokio as fs;
+use rustc_version::VersionMeta;
+
+use toml_edit::{Array, DocumentMut, value};
+
+use super::build::{
+    CARGO_CONFIG_PATH, EJECTED_TARGET_PATH, INSTALLED_TARGET_PATH, builtin_target_spec, rustc_cmd,
+};
+use crate::errors::CliError;
+
+/// Writes the built-in target spec to `output` (or [`EJECTED_TARGET_PATH`]) so that it can
+/// be customized.
+///
+/// Builds use the spec at [`EJECTED_TARGET_PATH`] whenever it exists. A spec ejected
+/// anywhere else must be passed to builds with `--target-spec`.
+pub async fn eject(
+    path: &Utf8Path,
+    output: Option<&Utf8Path>,
+    force: bool,
+) -> Result<(), CliError> {
+    let default_output = path.join(EJECTED_TARGET_PATH);
+    let output = output.unwrap_or(&default_output);
+
+    if output.exists() && !force {
+        return Err(CliError::TargetSpecExists(output.to_path_buf()));
+    }
+
+    let rustc_version_meta = VersionMeta::for_command(rustc_cmd(path))?;
+    if let Some(parent) = output.parent()
+        && !parent.as_str().is_empty()
+    {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(output, builtin_target_spec(&rustc_version_meta)).await?;
+
+    println!("     \x1b[1;92mEjected\x1b[0m target spec to {output}");
+    if output != default_output {
+        println!("Pass `--target-spec {output}` when building to use it.");
+    }
+
+    Ok(())
+}
+
+/// Writes the built-in target spec to [`INSTALLED_TARGET_PATH`] and sets it as the build
+/// target in the project's `.cargo/config.toml`, along with the `build-std` settings it
+/// needs.
+///
+/// This lets plain `cargo build` (and tools like rust-analyzer) build for the brain.
+/// `cargo v5 build` uses the configured spec as-is instead of regenerating it.
+pub async fn install(path: &Utf8Path, force: bool) -> Result<(), CliError> {
+    let output = path.join(INSTALLED_TARGET_PATH);
+    if output.exists() && !force {
+        return Err(CliError::TargetSpecExists(output));
+    }
+
+    let rustc_version_meta = VersionMeta::for_command(rustc_cmd(path))?;
+    fs::create_dir_all(output.parent().unwrap()).await?;
+    fs::write(&output, builtin_target_spec(&rustc_version_meta)).await?;
+
+    let config_path = path.join(CARGO_CONFIG_PATH);
+    let mut config = match fs::read_to_string(&config_path).await {
+        Ok(config) => config.parse::<DocumentMut>()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    config["build"].or_insert(toml_edit::table())["target"] = value(INSTALLED_TARGET_PATH);
+    let unstable = config["unstable"].or_insert(toml_edit::table());
+    if unstable.get("build-std").is_none() {
+        unstable["build-std"] = value(Array::from_iter(["core", "alloc", "compiler_builtins"]));
+    }
+    if unstable.get("build-std-features").is_none() {
+        unstable["build-std-features"] = value(Array::from_iter(["compiler-builtins-mem"]));
+    }
+    fs::write(&config_path, config.to_string()).await?;
+
+    println!("   \x1b[1;92mInstalled\x1b[0m target spec to {output}");
+    println!("  \x1b[1;92mConfigured\x1b[0m `build.target` in {config_path}");
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::process::Command;
+
+use cargo_metadata::camino::Utf8Path;
+use clap::Args;
+use rustc_version::VersionMeta;
+use tokio::task::block_in_place;
+
+use super::build::{cargo_bin, rustc_cmd};
+use crate::errors::CliError;
+
+/// Options for `cargo v5 test`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct TestOpts {
+    /// Test name filters and other arguments forwarded to `cargo test`, like `--release`.
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "CARGO-TEST-OPTIONS"
+    )]
+    args: Vec<String>,
+}
+
+/// The rustup toolchain that the project at `path` uses, if rustup is installed.
+fn active_toolchain(path: &Utf8Path) -> Option<String> {
+    let output = Command::new("rustup")
+        .current_dir(path)
+        .args(["show", "active-toolchain"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    // The toolchain name is followed by why it's active, e.g. "(overridden by ...)".
+    String::from_utf8(output.stdout)
+        .ok()?
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
+
+/// Runs the project's tests on the host, rather than building them for the brain.
+///
+/// vexide projects set the V5 target and `build-std` in `.cargo/config.toml`, which cargo
+/// only reads from the directory it's run in. `cargo test` is run from outside the project
+/// so those settings are skipped, while the project's toolchain is kept by passing it on
+/// through `RUSTUP_TOOLCHAIN`.
+pub fn test(path: &Utf8Path, opts: TestOpts) -> Result<(), CliError> {
+    let manifest_path = path.canonicalize_utf8()?.join("Cargo.toml");
+    if !manifest_path.exists() {
+        return Err(CliError::ManifestNotFound(manifest_path));
+    }
+    let host = VersionMeta::for_command(rustc_cmd(path))?.host;
+
+    let mut test_cmd = Command::new(cargo_bin());
+    test_cmd
+        .current_dir(std::env::temp_dir())
+        .arg("test")
+        .arg("--manifest-path")
+        .arg(&manifest_path)
+        .arg("--target")
+        .arg(host)
+        .args(opts.args);
+
+    // cargo-v5 already runs with the project's toolchain when rustup starts it as `cargo v5`.
+    if std::env::var_os("RUSTUP_TOOLCHAIN").is_none()
+        && let Some(toolchain) = active_toolchain(path)
+    {
+        test_cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+
+    let status = block_in_place(|| test_cmd.status())?;
+    if !status.success() {
+        return Err(CliError::TestsFailed);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,24 @@
-use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    PackageId,
+};
 use clap::{Args, ValueEnum};
 use flate2::{Compression, GzBuilder};
+use humansize::{format_size, BINARY};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use inquire::{
     validator::{ErrorMessage, Validation},
     CustomType,
 };
-use tokio::{fs::File, io::AsyncWriteExt, spawn, sync::Mutex, task::block_in_place, time::Instant};
+use log::{info, warn};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    spawn,
+    sync::Mutex,
+    task::{block_in_place, JoinHandle},
+    time::Instant,
+};
 
 use std::{
     io::{ErrorKind, Write},
@@ -16,7 +28,8 @@ use std::{
 
 use vex_v5_serial::{
     commands::file::{
-        LinkedFile, Program, ProgramIniConfig, Project, UploadFile, USER_PROGRAM_LOAD_ADDR,
+        DownloadFile, LinkedFile, Program, ProgramIniConfig, Project, UploadFile,
+        USER_PROGRAM_LOAD_ADDR,
     },
     connection::{
         serial::{SerialConnection, SerialError},
@@ -26,8 +39,9 @@ use vex_v5_serial::{
     packets::{
         cdc2::Cdc2Ack,
         file::{
-            ExtensionType, FileExitAction, FileMetadata, FileVendor, GetFileMetadataPacket,
-            GetFileMetadataPayload, GetFileMetadataReplyPacket, GetFileMetadataReplyPayload,
+            ExtensionType, FileExitAction, FileMetadata, FileTransferTarget, FileVendor,
+            GetFileMetadataPacket, GetFileMetadataPayload, GetFileMetadataReplyPacket,
+            GetFileMetadataReplyPayload,
         },
         radio::RadioChannel,
     },
@@ -37,19 +51,22 @@ use vex_v5_serial::{
 };
 
 use crate::{
-    connection::{open_connection, switch_radio_channel},
+    connection::{
+        is_connection_wireless, open_connection, running_program, stop_program,
+        switch_radio_channel, DEFAULT_TIMEOUT,
+    },
     errors::CliError,
-    metadata::Metadata,
+    metadata::{Metadata, ProgramMetadata},
 };
 
-use super::build::{build, objcopy, CargoOpts};
+use super::build::{build, objcopy, single_output, BuildOutput, CargoOpts};
 
 /// Options used to control the behavior of a program upload
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone, Default)]
 pub struct UploadOpts {
-    /// Program slot.
-    #[arg(short, long)]
-    pub slot: Option<u8>,
+    /// Program slot, or a comma-separated list of slots to upload the same program to.
+    #[arg(short, long, value_name = "SLOT", value_delimiter = ',')]
+    pub slot: Vec<u8>,
 
     /// The name of the program.
     #[arg(long)]
@@ -79,6 +96,37 @@ pub struct UploadOpts {
     #[arg(long)]
     pub cold: bool,
 
+    /// Stop the program running on the brain instead of refusing to upload.
+    #[arg(long)]
+    pub force: bool,
+
+    /// When uploading to several slots, keep going after an upload to one of them fails.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Read the program back from the brain after uploading it, and fail if its checksum
+    /// doesn't match what was sent.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// How long to wait for the device to respond when connecting.
+    ///
+    /// Set from the global `--timeout` argument.
+    #[arg(skip)]
+    pub timeout: Option<Duration>,
+
+    /// The serial port of the device to upload to, instead of the first one found.
+    ///
+    /// Set from `CARGO_V5_PORT`.
+    #[arg(skip)]
+    pub port: Option<String>,
+
+    /// Never prompt for a slot number, failing instead if none is configured.
+    ///
+    /// Set from the global `--yes` argument.
+    #[arg(skip)]
+    pub yes: bool,
+
     /// Arguments forwarded to `cargo`.
     #[clap(flatten)]
     pub cargo_opts: CargoOpts,
@@ -159,6 +207,23 @@ pub const PROGRESS_CHARS: &str = "⣿⣦⣀";
 
 const DIFFERENTIAL_UPLOAD_MAX_SIZE: usize = 0x200000;
 
+/// How many times an upload to a slot is retried after the connection drops partway through.
+const UPLOAD_RETRIES: usize = 3;
+
+/// How long to wait for a dropped serial port to come back before reopening it.
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Whether `err` is the kind of USB hiccup that an upload can recover from by reconnecting.
+fn is_transient(err: &CliError) -> bool {
+    matches!(
+        err,
+        CliError::DeviceTimeout { .. }
+            | CliError::SerialError(
+                SerialError::IoError(_) | SerialError::Timeout | SerialError::SerialportError(_)
+            )
+    )
+}
+
 /// Upload a program to the brain.
 pub async fn upload_program(
     connection: &mut SerialConnection,
@@ -172,8 +237,11 @@ pub async fn upload_program(
     compress: bool,
     cold: bool,
     upload_strategy: UploadStrategy,
+    verify: bool,
 ) -> Result<(), CliError> {
     let multi_progress = MultiProgress::new();
+    // The name, size, and checksum of each program file sent, for `verify`.
+    let mut uploaded_files: Vec<(String, usize, u32)> = Vec::new();
 
     let slot_file_name = format!("slot_{}.bin", slot);
     let ini_file_name = format!("slot_{}.ini", slot);
@@ -267,6 +335,16 @@ pub async fn upload_program(
                     .with_message(slot_file_name.clone()),
             ));
 
+            let mut data = tokio::fs::read(path).await?;
+            if compress {
+                gzip_compress(&mut data);
+            }
+            uploaded_files.push((
+                slot_file_name.clone(),
+                data.len(),
+                VEX_CRC32.checksum(&data),
+            ));
+
             // Upload the program.
             connection
                 .execute_command(UploadFile {
@@ -283,15 +361,7 @@ pub async fn upload_program(
                         },
                     },
                     vendor: Some(FileVendor::User),
-                    data: {
-                        let mut data = tokio::fs::read(path).await?;
-
-                        if compress {
-                            gzip_compress(&mut data);
-                        }
-
-                        data
-                    },
+                    data,
                     target: None,
                     load_addr: USER_PROGRAM_LOAD_ADDR,
                     linked_file: None,
@@ -376,6 +446,11 @@ pub async fn upload_program(
                 }
 
                 gzip_compress(&mut patch);
+                uploaded_files.push((
+                    slot_file_name.clone(),
+                    patch.len(),
+                    VEX_CRC32.checksum(&patch),
+                ));
 
                 connection
                     .execute_command(UploadFile {
@@ -460,9 +535,9 @@ pub async fn upload_program(
                                 gzip_compress(&mut base_data);
                             }
 
-                            base_file
-                                .write_all(&VEX_CRC32.checksum(&base_data).to_le_bytes())
-                                .await?;
+                            let crc = VEX_CRC32.checksum(&base_data);
+                            base_file.write_all(&crc.to_le_bytes()).await?;
+                            uploaded_files.push((base_file_name.clone(), base_data.len(), crc));
 
                             base_data
                         },
@@ -512,6 +587,12 @@ pub async fn upload_program(
         }
     }
 
+    if verify {
+        for (file_name, size, crc) in uploaded_files {
+            verify_upload(connection, &file_name, size, crc).await?;
+        }
+    }
+
     if after == AfterUpload::Run {
         println!("     \x1b[1;92mRunning\x1b[0m `{}`", slot_file_name);
     }
@@ -519,6 +600,40 @@ pub async fn upload_program(
     Ok(())
 }
 
+/// Reads `file_name` back from the brain and checks that it has the checksum of the `size`
+/// bytes that were uploaded to it.
+async fn verify_upload(
+    connection: &mut SerialConnection,
+    file_name: &str,
+    size: usize,
+    crc: u32,
+) -> Result<(), CliError> {
+    let fixed_name = FixedString::new(file_name.to_string()).unwrap();
+    let Some(metadata) =
+        brain_file_metadata(connection, fixed_name.clone(), FileVendor::User).await?
+    else {
+        return Err(CliError::VerificationFailed(file_name.to_string()));
+    };
+
+    let data = connection
+        .execute_command(DownloadFile {
+            file_name: fixed_name,
+            size: size as u32,
+            vendor: FileVendor::User,
+            target: Some(FileTransferTarget::Qspi),
+            load_addr: metadata.load_address,
+            progress_callback: None,
+        })
+        .await?;
+
+    if data.len() != size || VEX_CRC32.checksum(&data) != crc {
+        return Err(CliError::VerificationFailed(file_name.to_string()));
+    }
+
+    println!("    \x1b[1;92mVerified\x1b[0m {file_name} (CRC32 {crc:#010x})");
+    Ok(())
+}
+
 fn build_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
     let mut patch = Vec::new();
 
@@ -533,7 +648,7 @@ fn build_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
     patch
 }
 
-async fn brain_file_metadata(
+pub(crate) async fn brain_file_metadata(
     connection: &mut SerialConnection,
     file_name: FixedString<23>,
     vendor: FileVendor,
@@ -578,36 +693,41 @@ fn build_progress_callback(
 
 /// Apply gzip compression to the given data
 fn gzip_compress(data: &mut Vec<u8>) {
+    let original_size = data.len();
     let mut encoder = GzBuilder::new().write(Vec::new(), Compression::best());
     encoder.write_all(data).unwrap();
     *data = encoder.finish().unwrap();
+
+    eprintln!(
+        "  \x1b[1;92mCompressed\x1b[0m {} to {} ({:.1}% smaller)",
+        format_size(original_size, BINARY),
+        format_size(data.len(), BINARY),
+        100.0 * (1.0 - data.len() as f64 / original_size.max(1) as f64)
+    );
 }
 
 pub async fn upload(
     path: &Utf8Path,
-    UploadOpts {
-        file,
-        slot,
-        name,
-        description,
-        icon,
-        uncompressed,
-        cargo_opts,
-        upload_strategy,
-        cold,
-    }: UploadOpts,
+    mut opts: UploadOpts,
     after: AfterUpload,
-) -> miette::Result<SerialConnection> {
+) -> Result<SerialConnection, CliError> {
     // Try to open a serialport in the background while we build.
-    let connection_task = spawn(open_connection());
+    let connection_task = spawn(open_connection(
+        opts.port.clone(),
+        opts.timeout.unwrap_or(DEFAULT_TIMEOUT),
+    ));
 
     // Get the build artifact we'll be uploading with.
     //
     // The user either directly passed an file through the `--file` argument, or they didn't and we need to run
     // `cargo build`.
-    let (artifact, package_id) = if let Some(file) = file {
+    let (artifact, package_id, manifest_path) = if let Some(file) = opts.file.take() {
+        // There's no build output to get a manifest from, but the project's metadata is
+        // still used for defaults.
+        let manifest_path = opts.cargo_opts.manifest_path(path);
+
         if file.extension() == Some("bin") {
-            (file, None)
+            (file, None, manifest_path)
         } else {
             // If a BIN file wasn't provided, we'll attempt to objcopy it as if it were an ELF.
             let binary = objcopy(
@@ -621,22 +741,126 @@ pub async fn upload(
             tokio::fs::write(&binary_path, binary)
                 .await
                 .map_err(|e| CliError::IoError(e))?;
-            println!("     \x1b[1;92mObjcopy\x1b[0m {}", binary_path);
+            eprintln!("     \x1b[1;92mObjcopy\x1b[0m {}", binary_path);
 
-            (binary_path, None)
+            (binary_path, None, manifest_path)
         }
     } else {
         // Run cargo build, then objcopy.
-        build(path, cargo_opts)
-            .await?
-            .map(|output| (output.bin_artifact, Some(output.package_id)))
-            .ok_or(CliError::NoArtifact)?
+        let output = single_output(build(path, opts.cargo_opts.clone()).await?)?;
+        (
+            output.bin_artifact,
+            Some(output.package_id),
+            output.manifest_path,
+        )
+    };
+
+    upload_artifact(
+        connection_task,
+        artifact,
+        package_id,
+        manifest_path,
+        opts,
+        after,
+    )
+    .await
+}
+
+/// Build and upload every program in `package.metadata.v5.programs` to its own slot.
+///
+/// Programs are uploaded in the order they're listed, and `after` only applies to the last
+/// one.
+pub async fn upload_programs(
+    path: &Utf8Path,
+    opts: UploadOpts,
+    after: AfterUpload,
+) -> Result<SerialConnection, CliError> {
+    let metadata = block_in_place(|| {
+        cargo_metadata::MetadataCommand::new()
+            .manifest_path(opts.cargo_opts.manifest_path(path))
+            .no_deps()
+            .exec()
+    })
+    .map_err(CliError::CargoMetadataError)?;
+    let package = metadata
+        .root_package()
+        .or(metadata.packages.first())
+        .ok_or(CliError::NoPrograms)?;
+
+    let programs = ProgramMetadata::list(package)?;
+    let Some((last, rest)) = programs.split_last() else {
+        Err(CliError::NoPrograms)?
+    };
+
+    let program_opts = |program: &ProgramMetadata| {
+        let mut opts = opts.clone();
+        opts.slot = vec![program.slot];
+        opts.name = Some(program.name.clone().unwrap_or_else(|| program.bin.clone()));
+        opts.cargo_opts.bin(&program.bin);
+        opts
     };
 
+    for program in rest {
+        // Only one connection to the brain can be open at a time.
+        drop(upload(path, program_opts(program), AfterUpload::None).await?);
+    }
+    upload(path, program_opts(last), after).await
+}
+
+/// Upload the output of a previous [`build`] without building again.
+pub async fn upload_build_output(
+    output: BuildOutput,
+    opts: UploadOpts,
+    after: AfterUpload,
+) -> Result<SerialConnection, CliError> {
+    let connection_task = spawn(open_connection(
+        opts.port.clone(),
+        opts.timeout.unwrap_or(DEFAULT_TIMEOUT),
+    ));
+
+    upload_artifact(
+        connection_task,
+        output.bin_artifact,
+        Some(output.package_id),
+        output.manifest_path,
+        opts,
+        after,
+    )
+    .await
+}
+
+async fn upload_artifact(
+    connection_task: JoinHandle<Result<SerialConnection, CliError>>,
+    artifact: Utf8PathBuf,
+    package_id: Option<PackageId>,
+    manifest_path: Utf8PathBuf,
+    UploadOpts {
+        slot,
+        name,
+        description,
+        icon,
+        uncompressed,
+        upload_strategy,
+        cold,
+        continue_on_error,
+        verify,
+        force,
+        timeout,
+        port,
+        yes,
+        ..
+    }: UploadOpts,
+    after: AfterUpload,
+) -> Result<SerialConnection, CliError> {
     // We'll use `cargo-metadata` to parse the output of `cargo metadata` and find valid `Cargo.toml`
     // files in the workspace directory.
-    let cargo_metadata =
-        block_in_place(|| cargo_metadata::MetadataCommand::new().no_deps().exec()).ok();
+    let cargo_metadata = block_in_place(|| {
+        cargo_metadata::MetadataCommand::new()
+            .manifest_path(manifest_path)
+            .no_deps()
+            .exec()
+    })
+    .ok();
 
     // Find which package we're being built from, if we're being built from a package at all.
     let package = cargo_metadata.and_then(|metadata| {
@@ -670,57 +894,190 @@ pub async fn upload(
     //
     // - Check for the `package.metadata.v5.slot` field in Cargo.toml.
     // - If that doesn't exist, directly prompt the user asking what slot to upload to.
-    let slot = slot
-        .or(metadata.and_then(|m| m.slot))
-        .or_else(|| {
-            CustomType::<u8>::new("Choose a program slot to upload to:")
-                .with_validator(|slot: &u8| {
-                    Ok(if (1..=8).contains(slot) {
-                        Validation::Valid
-                    } else {
-                        Validation::Invalid(ErrorMessage::Custom("Slot out of range".to_string()))
+    let slots = if slot.is_empty() {
+        let slot = metadata
+            .as_ref()
+            .and_then(|m| m.slot)
+            .or_else(|| {
+                if yes {
+                    return None;
+                }
+                CustomType::<u8>::new("Choose a program slot to upload to:")
+                    .with_validator(|slot: &u8| {
+                        Ok(if (1..=8).contains(slot) {
+                            Validation::Valid
+                        } else {
+                            Validation::Invalid(ErrorMessage::Custom(
+                                "Slot out of range".to_string(),
+                            ))
+                        })
                     })
-                })
-                .with_help_message("Type a slot number from 1 to 8, inclusive")
-                .prompt()
-                .ok()
-        })
-        .ok_or(CliError::NoSlot)?;
+                    .with_help_message("Type a slot number from 1 to 8, inclusive")
+                    .prompt()
+                    .ok()
+            })
+            .ok_or(CliError::NoSlot)?;
+        vec![slot]
+    } else {
+        slot
+    };
 
     // Ensure [1, 8] range bounds for slot number
-    if !(1..=8).contains(&slot) {
+    if slots.iter().any(|slot| !(1..=8).contains(slot)) {
         Err(CliError::SlotOutOfRange)?;
     }
 
     // Switch the radio to the download channel if the controller is wireless.
     switch_radio_channel(&mut connection, RadioChannel::Download).await?;
 
-    // Pass information to the upload routine.
-    upload_program(
-        &mut connection,
-        &artifact,
-        after,
-        slot,
-        name.or(package.as_ref().map(|pkg| pkg.name.to_string()))
-            .unwrap_or("cargo-v5".to_string()),
-        description
-            .or(package.as_ref().and_then(|pkg| pkg.description.clone()))
-            .unwrap_or("Uploaded with cargo-v5.".to_string()),
-        icon.or(metadata.and_then(|metadata| metadata.icon))
-            .unwrap_or_default(),
-        "Rust".to_string(), // `program_type` hardcoded for now, maybe configurable in the future.
-        match uncompressed {
-            Some(val) => !val,
-            None => metadata
-                .and_then(|metadata| metadata.compress)
-                .unwrap_or(true),
-        },
-        cold,
-        upload_strategy
-            .or(metadata.and_then(|metadata| metadata.upload_strategy))
-            .unwrap_or_default(),
-    )
-    .await?;
+    // Transfers to a brain that's busy running a program tend to fail partway through.
+    if let Some(program) = running_program(&mut connection).await? {
+        let program = match program {
+            1..=8 => format!("The program in slot {program}"),
+            _ => "A built-in program".to_string(),
+        };
+        if !force {
+            return Err(CliError::ProgramRunning(program));
+        }
+
+        info!("{program} is running, stopping it before uploading...");
+        stop_program(&mut connection).await?;
+    }
+
+    let upload_strategy = upload_strategy
+        .or(metadata
+            .as_ref()
+            .and_then(|metadata| metadata.upload_strategy))
+        .unwrap_or_default();
+
+    if is_connection_wireless(&mut connection)
+        .await
+        .unwrap_or(false)
+    {
+        let size = tokio::fs::metadata(&artifact)
+            .await
+            .map(|metadata| format_size(metadata.len(), BINARY))
+            .unwrap_or_else(|_| "unknown size".to_string());
+        warn!(
+            "Uploading {size} over a wireless controller connection. This is much slower than \
+             connecting to the brain directly over USB."
+        );
+        if upload_strategy == UploadStrategy::Monolith || cold {
+            warn!("Differential uploads (without `--cold`) send far less data over the radio.");
+        }
+    }
+
+    let name = name
+        .or(metadata.as_ref().and_then(|metadata| metadata.name.clone()))
+        .or(package.as_ref().map(|pkg| pkg.name.to_string()))
+        .unwrap_or("cargo-v5".to_string());
+    let description = description
+        .or(metadata
+            .as_ref()
+            .and_then(|metadata| metadata.description.clone()))
+        .or(package.as_ref().and_then(|pkg| pkg.description.clone()))
+        .unwrap_or("Uploaded with cargo-v5.".to_string());
+    let icon = icon
+        .or(metadata.as_ref().and_then(|metadata| metadata.icon))
+        .unwrap_or_default();
+    let compress = match uncompressed {
+        Some(val) => !val,
+        None => metadata
+            .as_ref()
+            .and_then(|metadata| metadata.compress)
+            .unwrap_or(true),
+    };
+
+    // Every slot is uploaded over the same connection. Only the last slot's program is run
+    // afterwards, since starting a program would just be interrupted by the next upload.
+    let mut failed_slots = Vec::new();
+    for (i, &slot) in slots.iter().enumerate() {
+        let mut attempt = 0;
+        let result = loop {
+            let result = upload_program(
+                &mut connection,
+                &artifact,
+                if i + 1 == slots.len() {
+                    after
+                } else {
+                    AfterUpload::None
+                },
+                slot,
+                name.clone(),
+                description.clone(),
+                icon,
+                "Rust".to_string(), // `program_type` hardcoded for now, maybe configurable in the future.
+                compress,
+                cold,
+                upload_strategy,
+                verify,
+            )
+            .await;
+
+            // The brain forgets about a file transfer once the connection drops, so there's
+            // no offset to resume from. The upload to this slot is started over instead.
+            match result {
+                Err(mut err) if attempt < UPLOAD_RETRIES && is_transient(&err) => {
+                    // Uploading over the dropped connection would only fail again, so the
+                    // port is reopened first. Each try at reopening it uses up a retry.
+                    let reconnected = loop {
+                        attempt += 1;
+                        eprintln!(
+                            "    \x1b[1;93mRetrying\x1b[0m slot {slot} ({attempt}/{UPLOAD_RETRIES}): {err}"
+                        );
+                        tokio::time::sleep(UPLOAD_RETRY_DELAY).await;
+
+                        let reconnect = async {
+                            let mut connection =
+                                open_connection(port.clone(), timeout.unwrap_or(DEFAULT_TIMEOUT))
+                                    .await?;
+                            switch_radio_channel(&mut connection, RadioChannel::Download).await?;
+                            Ok::<_, CliError>(connection)
+                        };
+                        match reconnect.await {
+                            Ok(new_connection) => break Ok(new_connection),
+                            // The port may not have come back yet.
+                            Err(reconnect_err)
+                                if attempt < UPLOAD_RETRIES
+                                    && (is_transient(&reconnect_err)
+                                        || matches!(reconnect_err, CliError::NoDevice)) =>
+                            {
+                                err = reconnect_err;
+                            }
+                            Err(reconnect_err) => break Err(reconnect_err),
+                        }
+                    };
+
+                    match reconnected {
+                        Ok(new_connection) => connection = new_connection,
+                        Err(err) => break Err(err),
+                    }
+                }
+                result => break result,
+            }
+        };
+
+        match result {
+            Ok(()) if slots.len() > 1 => {
+                eprintln!("    \x1b[1;92mUploaded\x1b[0m slot {slot}");
+            }
+            Ok(()) => {}
+            Err(err) if continue_on_error && slots.len() > 1 => {
+                eprintln!("      \x1b[1;91mFailed\x1b[0m slot {slot}: {err}");
+                failed_slots.push(slot);
+            }
+            Err(err) => Err(err)?,
+        }
+    }
+
+    if !failed_slots.is_empty() {
+        let failed_slots = failed_slots
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(CliError::SlotUploadsFailed(failed_slots))?;
+    }
 
     Ok(connection)
 }
@@ -0,0 +1,61 @@
+use std::process::Command;
+
+use cargo_metadata::camino::Utf8Path;
+use serde::Serialize;
+
+use super::build::{cargo_bin, rustc_cmd};
+
+/// What `cargo v5 version` reports, with a stable schema for `--json`.
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    /// The commit cargo-v5 was built from, if it was built from a git checkout.
+    commit: Option<&'static str>,
+    /// The toolchain that builds the project, with `rust-toolchain.toml` taken into account.
+    rustc: Option<String>,
+    cargo: Option<String>,
+    host: Option<String>,
+}
+
+/// Runs `cargo --version` from `path`, so the project's toolchain is the one asked.
+fn cargo_version(path: &Utf8Path) -> Option<String> {
+    let output = Command::new(cargo_bin())
+        .current_dir(path)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Prints the version of cargo-v5 and of the toolchain used for the project at `path`.
+///
+/// Tools that can't be found are printed as `not found`, or `null` with `json`.
+pub fn version(path: &Utf8Path, json: bool) {
+    let rustc = rustc_version::VersionMeta::for_command(rustc_cmd(path)).ok();
+    let info = VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: option_env!("CARGO_V5_COMMIT"),
+        rustc: rustc
+            .as_ref()
+            .map(|rustc| rustc.short_version_string.clone()),
+        cargo: cargo_version(path),
+        host: rustc.map(|rustc| rustc.host),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&info).unwrap());
+        return;
+    }
+
+    match info.commit {
+        Some(commit) => println!("cargo-v5 {} ({commit})", info.version),
+        None => println!("cargo-v5 {}", info.version),
+    }
+
+    let not_found = || "not found".to_string();
+    println!("rustc: {}", info.rustc.unwrap_or_else(not_found));
+    println!("cargo: {}", info.cargo.unwrap_or_else(not_found));
+    println!("host: {}", info.host.unwrap_or_else(not_found));
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use cargo_metadata::camino::Utf8Path;
+use clap::ValueEnum;
+use tokio::{task::block_in_place, time::sleep};
+
+use super::{
+    build::build,
+    upload::{AfterUpload, UploadOpts, upload},
+};
+
+/// How often the project is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long files must stay unchanged before a rebuild starts.
+///
+/// Editors often write several files (or the same file several times) when saving, so
+/// this keeps one save from triggering multiple rebuilds.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Action to run after each successful rebuild in watch mode.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Upload the program to the brain.
+    Upload,
+}
+
+/// Records the modification time of every watched file in the project.
+///
+/// Only `Cargo.toml`, `build.rs`, and the `src/` directory are watched, so build output in
+/// `target/` never triggers a rebuild.
+fn snapshot(path: &Utf8Path) -> BTreeMap<PathBuf, SystemTime> {
+    let mut files = BTreeMap::new();
+
+    for file in ["Cargo.toml", "build.rs"] {
+        let file = path.join(file).into_std_path_buf();
+        if let Ok(modified) = std::fs::metadata(&file).and_then(|metadata| metadata.modified()) {
+            files.insert(file, modified);
+        }
+    }
+    snapshot_dir(path.join("src").as_std_path(), &mut files);
+
+    files
+}
+
+fn snapshot_dir(dir: &Path, files: &mut BTreeMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        // Skip hidden files, such as editor swap files.
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            snapshot_dir(&entry.path(), files);
+        } else if let Ok(modified) = metadata.modified() {
+            files.insert(entry.path(), modified);
+        }
+    }
+}
+
+/// Builds the project (and runs `action`) every time its sources change.
+///
+/// Failed builds and uploads are reported without stopping the watcher. This runs until
+/// the process is interrupted.
+pub async fn watch(path: &Utf8Path, opts: UploadOpts, action: Option<WatchAction>) -> ! {
+    let mut last_snapshot = block_in_place(|| snapshot(path));
+
+    loop {
+        let result = match action {
+            Some(WatchAction::Upload) => upload(path, opts.clone(), AfterUpload::None)
+                .await
+                .map(|_| ()),
+            None => build(path, opts.cargo_opts.clone()).await.map(|_| ()),
+        };
+        if let Err(err) = result {
+            eprintln!("Error: {:?}", miette::Report::new(err));
+        }
+
+        println!("    \x1b[1;96mWatching\x1b[0m {path} for changes (press Ctrl+C to stop)");
+
+        // Wait for something to change...
+        loop {
+            sleep(POLL_INTERVAL).await;
+            let snapshot = block_in_place(|| snapshot(path));
+            if snapshot != last_snapshot {
+                last_snapshot = snapshot;
+                break;
+            }
+        }
+
+        // ...then for it to stop changing.
+        loop {
+            sleep(DEBOUNCE).await;
+            let snapshot = block_in_place(|| snapshot(path));
+            if snapshot == last_snapshot {
+                break;
+            }
+            last_snapshot = snapshot;
+        }
+    }
+}
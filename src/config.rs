@@ -0,0 +1,102 @@
+use std::env;
+
+use cargo_metadata::camino::Utf8PathBuf;
+use clap::ValueEnum;
+
+use crate::{
+    commands::upload::{ProgramIcon, UploadStrategy},
+    errors::CliError,
+};
+
+/// Partial configuration loaded from `CARGO_V5_*` (and a few of cargo's) environment variables.
+///
+/// Each field mirrors a CLI flag. Values here are only used when the corresponding
+/// flag wasn't passed, so the CLI always takes precedence.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct EnvConfig {
+    /// `CARGO_V5_SLOT` (or `CARGO_V5_DEFAULT_SLOT`)
+    pub slot: Option<u8>,
+    /// `CARGO_V5_ICON`
+    pub icon: Option<ProgramIcon>,
+    /// `CARGO_V5_UPLOAD_STRATEGY`
+    pub upload_strategy: Option<UploadStrategy>,
+    /// `CARGO_V5_OFFLINE`
+    pub offline: Option<bool>,
+    /// `CARGO_V5_TEMPLATE_URL`
+    pub template_url: Option<String>,
+    /// `CARGO_V5_TEMPLATE_CACHE_TTL`
+    pub template_cache_ttl: Option<u64>,
+    /// `CARGO_V5_NO_NIGHTLY_CHECK`
+    pub no_nightly_check: Option<bool>,
+    /// `CARGO_V5_PORT`
+    pub port: Option<String>,
+    /// `CARGO_MANIFEST_DIR`, which cargo sets for `cargo run`
+    pub manifest_dir: Option<Utf8PathBuf>,
+}
+
+fn var(name: &'static str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn parse_bool(name: &'static str, value: String) -> Result<bool, CliError> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(CliError::InvalidEnvVar {
+            name,
+            value,
+            expected: "a boolean",
+        }),
+    }
+}
+
+/// Reads all supported `CARGO_V5_*` environment variables.
+///
+/// Unset or empty variables are left as `None`. A variable that is set to an unparsable
+/// value is reported as an error rather than silently ignored.
+pub fn from_env() -> Result<EnvConfig, CliError> {
+    let slot_var = ["CARGO_V5_SLOT", "CARGO_V5_DEFAULT_SLOT"]
+        .into_iter()
+        .find_map(|name| var(name).map(|value| (name, value)));
+
+    Ok(EnvConfig {
+        slot: slot_var
+            .map(|(name, value)| {
+                value.parse::<u8>().map_err(|_| CliError::InvalidEnvVar {
+                    name,
+                    value,
+                    expected: "a slot number",
+                })
+            })
+            .transpose()?,
+        icon: var("CARGO_V5_ICON")
+            .map(|value| {
+                ProgramIcon::from_str(&value, false).map_err(|_| CliError::InvalidIcon(value))
+            })
+            .transpose()?,
+        upload_strategy: var("CARGO_V5_UPLOAD_STRATEGY")
+            .map(|value| {
+                UploadStrategy::from_str(&value, false)
+                    .map_err(|_| CliError::InvalidUploadStrategy(value))
+            })
+            .transpose()?,
+        offline: var("CARGO_V5_OFFLINE")
+            .map(|value| parse_bool("CARGO_V5_OFFLINE", value))
+            .transpose()?,
+        template_url: var("CARGO_V5_TEMPLATE_URL"),
+        template_cache_ttl: var("CARGO_V5_TEMPLATE_CACHE_TTL")
+            .map(|value| {
+                value.parse::<u64>().map_err(|_| CliError::InvalidEnvVar {
+                    name: "CARGO_V5_TEMPLATE_CACHE_TTL",
+                    value,
+                    expected: "a number of seconds",
+                })
+            })
+            .transpose()?,
+        no_nightly_check: var("CARGO_V5_NO_NIGHTLY_CHECK")
+            .map(|value| parse_bool("CARGO_V5_NO_NIGHTLY_CHECK", value))
+            .transpose()?,
+        port: var("CARGO_V5_PORT"),
+        manifest_dir: var("CARGO_MANIFEST_DIR").map(Utf8PathBuf::from),
+    })
+}
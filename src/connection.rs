@@ -1,5 +1,5 @@
-use log::info;
-use std::time::Duration;
+use log::{info, warn};
+use std::{path::Path, time::Duration};
 use tokio::{select, task::spawn_blocking, time::sleep};
 use vex_v5_serial::{
     connection::{
@@ -7,6 +7,10 @@ use vex_v5_serial::{
         Connection,
     },
     packets::{
+        file::{
+            FileLoadAction, FileVendor, LoadFileActionPacket, LoadFileActionPayload,
+            LoadFileActionReplyPacket,
+        },
         radio::{
             GetRadioStatusPacket, GetRadioStatusReplyPacket, RadioChannel,
             SelectRadioChannelPacket, SelectRadioChannelPayload, SelectRadioChannelReplyPacket,
@@ -16,27 +20,106 @@ use vex_v5_serial::{
             GetSystemVersionReplyPacket, ProductType,
         },
     },
+    string::FixedString,
 };
 
 use crate::errors::CliError;
 
-pub async fn open_connection() -> miette::Result<SerialConnection> {
+/// Returns `true` if cargo-v5 seems to be running inside a container, where USB devices
+/// are only visible if they're explicitly passed through.
+fn in_container() -> bool {
+    if Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() {
+        return true;
+    }
+
+    std::fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| {
+        ["docker", "kubepods", "containerd", "libpod"]
+            .iter()
+            .any(|runtime| cgroup.contains(runtime))
+    })
+}
+
+/// How long to wait for a device to respond when connecting, unless `--timeout` is passed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for each attempt at the initial handshake.
+const HANDSHAKE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Opens a connection to the V5 device on `port` (from `CARGO_V5_PORT`), or the first one
+/// found.
+///
+/// The device must answer a handshake within `timeout`, so a brain in a bad state fails
+/// with [`CliError::DeviceTimeout`] instead of hanging later on.
+pub async fn open_connection(
+    port: Option<String>,
+    timeout: Duration,
+) -> Result<SerialConnection, CliError> {
     // Find all vex devices on serial ports.
     let devices = serial::find_devices().map_err(CliError::SerialError)?;
 
+    if devices.is_empty() && in_container() {
+        warn!(
+            "cargo-v5 appears to be running in a container. USB devices must be passed through \
+             to it (e.g. `docker run --device /dev/ttyACM0`) to be found. See \
+             https://docs.docker.com/reference/cli/docker/container/run/#device"
+        );
+    }
+
     // Open a connection to the device.
-    spawn_blocking(move || {
-        Ok(devices
-            .first()
-            .ok_or(CliError::NoDevice)?
-            .connect(Duration::from_secs(5))
-            .map_err(CliError::SerialError)?)
+    let mut connection = spawn_blocking(move || {
+        let device = match port {
+            Some(port) => devices
+                .iter()
+                .find(|device| {
+                    device.system_port() == port || device.user_port().as_ref() == Some(&port)
+                })
+                .ok_or(CliError::NoDeviceOnPort(port))?,
+            None => devices.first().ok_or(CliError::NoDevice)?,
+        };
+
+        device.connect(timeout).map_err(|err| {
+            // Serial ports on Linux belong to a group that new users usually aren't in.
+            let port = device.system_port();
+            if cfg!(unix)
+                && std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(&port)
+                    .is_err_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+            {
+                CliError::SerialPermissionDenied(port)
+            } else {
+                CliError::SerialError(err)
+            }
+        })
     })
     .await
-    .unwrap()
+    .unwrap()?;
+
+    let timeout_error = || CliError::DeviceTimeout {
+        timeout_ms: timeout.as_millis(),
+    };
+    let retries = (timeout.as_millis() / HANDSHAKE_INTERVAL.as_millis()).max(1) as usize;
+    tokio::time::timeout(
+        timeout,
+        connection.packet_handshake::<GetSystemVersionReplyPacket>(
+            HANDSHAKE_INTERVAL.min(timeout),
+            retries,
+            GetSystemVersionPacket::new(()),
+        ),
+    )
+    .await
+    .map_err(|_| timeout_error())?
+    .map_err(|err| match err {
+        SerialError::Timeout => timeout_error(),
+        other => CliError::SerialError(other),
+    })?;
+
+    Ok(connection)
 }
 
-async fn is_connection_wireless(connection: &mut SerialConnection) -> Result<bool, CliError> {
+/// Returns `true` if `connection` is to a controller that's wirelessly linked to the brain.
+pub async fn is_connection_wireless(connection: &mut SerialConnection) -> Result<bool, CliError> {
     let version = connection
         .packet_handshake::<GetSystemVersionReplyPacket>(
             Duration::from_millis(500),
@@ -58,6 +141,40 @@ async fn is_connection_wireless(connection: &mut SerialConnection) -> Result<boo
     Ok(!tethered && controller)
 }
 
+/// Returns the slot of the program currently running on the brain, if any.
+///
+/// Built-in programs are reported with the brain's own program numbers, which are above 8.
+pub async fn running_program(connection: &mut SerialConnection) -> Result<Option<u8>, CliError> {
+    let system_flags = connection
+        .packet_handshake::<GetSystemFlagsReplyPacket>(
+            Duration::from_millis(500),
+            1,
+            GetSystemFlagsPacket::new(()),
+        )
+        .await?
+        .try_into_inner()?;
+
+    Ok(Some(system_flags.current_program).filter(|&program| program != 0))
+}
+
+/// Stops the program running on the brain.
+pub async fn stop_program(connection: &mut SerialConnection) -> Result<(), CliError> {
+    connection
+        .packet_handshake::<LoadFileActionReplyPacket>(
+            Duration::from_secs(1),
+            2,
+            LoadFileActionPacket::new(LoadFileActionPayload {
+                vendor: FileVendor::User,
+                action: FileLoadAction::Stop,
+                file_name: FixedString::new(Default::default()).unwrap(),
+            }),
+        )
+        .await?
+        .try_into_inner()?;
+
+    Ok(())
+}
+
 pub async fn switch_radio_channel(
     connection: &mut SerialConnection,
     channel: RadioChannel,
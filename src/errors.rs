@@ -1,3 +1,4 @@
+use cargo_metadata::camino::Utf8PathBuf;
 use humansize::{format_size, BINARY};
 use image::ImageError;
 use miette::Diagnostic;
@@ -29,6 +30,17 @@ pub enum CliError {
     #[diagnostic(code(cargo_v5::malformed_response))]
     MalformedResponse,
 
+    #[cfg(feature = "fetch-template")]
+    #[error("GitHub's API rate limit was exceeded. Try again {retry_after}.")]
+    #[diagnostic(
+        code(cargo_v5::github_rate_limited),
+        help("Set `GITHUB_TOKEN` to make authenticated requests, which have a higher rate limit.")
+    )]
+    GitHubRateLimited {
+        /// When the rate limit resets, e.g. "after 14:05:00"
+        retry_after: String,
+    },
+
     #[error(transparent)]
     #[diagnostic(code(cargo_v5::image_error))]
     ImageError(#[from] ImageError),
@@ -58,6 +70,20 @@ pub enum CliError {
     )]
     SlotOutOfRange,
 
+    #[error("{0} is not a valid program slot.")]
+    #[diagnostic(
+        code(cargo_v5::invalid_slot),
+        help("The V5 brain only has eight program slots. Pass a `--slot` from 1-8.")
+    )]
+    InvalidSlot(u8),
+
+    #[error("'{0}' is not a valid author.")]
+    #[diagnostic(
+        code(cargo_v5::invalid_author),
+        help("Pass the author as `Name <email>`, e.g. `--author \"Jane <jane@example.com>\"`.")
+    )]
+    InvalidAuthor(String),
+
     // TODO: Add source spans.
     #[error("{0} is not a valid icon.")]
     #[diagnostic(
@@ -73,6 +99,46 @@ pub enum CliError {
     )]
     InvalidUploadStrategy(String),
 
+    #[error("Environment variable `{name}` is set to `{value}`, which is not {expected}.")]
+    #[diagnostic(
+        code(cargo_v5::invalid_env_var),
+        help("Unset `{name}` or change it to a valid value.")
+    )]
+    InvalidEnvVar {
+        /// Variable name
+        name: &'static str,
+
+        /// Value the variable was set to
+        value: String,
+
+        /// Description of what was expected
+        expected: &'static str,
+    },
+
+    #[error("vexide requires Nightly Rust features, but you're using stable.")]
+    #[diagnostic(
+        code(cargo_v5::nightly_required),
+        help(
+            "This can be fixed by running `rustup override set nightly`. If your toolchain is nightly-based but not reported as nightly, pass `--no-nightly-check`."
+        )
+    )]
+    NightlyRequired,
+
+    #[error("Build failed.")]
+    #[diagnostic(code(cargo_v5::build_failed))]
+    BuildFailed,
+
+    #[error("Tests failed.")]
+    #[diagnostic(code(cargo_v5::tests_failed))]
+    TestsFailed,
+
+    #[error("Erasing the program wasn't confirmed.")]
+    #[diagnostic(
+        code(cargo_v5::erase_not_confirmed),
+        help("Pass `--force` to erase the program without being asked.")
+    )]
+    EraseNotConfirmed,
+
     #[error("No slot number was provided.")]
     #[diagnostic(
         code(cargo_v5::no_slot),
@@ -80,6 +146,34 @@ pub enum CliError {
     )]
     NoSlot,
 
+    #[error("No project name was provided.")]
+    #[diagnostic(
+        code(cargo_v5::no_project_name),
+        help("Pass the project's name, like `cargo v5 new my-robot`.")
+    )]
+    NoProjectName,
+
+    #[error("No programs are listed in `package.metadata.v5.programs`.")]
+    #[diagnostic(
+        code(cargo_v5::no_programs),
+        help("List each program's `bin` and `slot` in `[[package.metadata.v5.programs]]`.")
+    )]
+    NoPrograms,
+
+    #[error("Uploading to slot(s) {0} failed.")]
+    #[diagnostic(
+        code(cargo_v5::slot_uploads_failed),
+        help("The other slots were uploaded. Check the errors above, then try those slots again.")
+    )]
+    SlotUploadsFailed(String),
+
+    #[error("{0} is running on the brain.")]
+    #[diagnostic(
+        code(cargo_v5::program_running),
+        help("Stop the program on the brain, or pass `--force` to stop it before uploading.")
+    )]
+    ProgramRunning(String),
+
     #[error("ELF build artifact not found. Is this a binary crate?")]
     #[diagnostic(
         code(cargo_v5::no_artifact),
@@ -87,6 +181,17 @@ pub enum CliError {
     )]
     NoArtifact,
 
+    #[error("The build produced more than one program: {}.", .0.join(", "))]
+    #[diagnostic(
+        code(cargo_v5::multiple_artifacts),
+        help("Choose the one to upload with `--bin <NAME>` or `--example <NAME>`.")
+    )]
+    MultipleArtifacts(Vec<String>),
+
+    #[error("A build task failed: {0}")]
+    #[diagnostic(code(cargo_v5::task_failed))]
+    TaskFailed(#[from] tokio::task::JoinError),
+
     #[error("No V5 devices found.")]
     #[diagnostic(
         code(cargo_v5::no_device),
@@ -94,6 +199,100 @@ pub enum CliError {
     )]
     NoDevice,
 
+    #[error("The V5 device didn't respond within {timeout_ms}ms.")]
+    #[diagnostic(
+        code(cargo_v5::device_timeout),
+        help("Try power cycling your brain or controller, or pass a longer `--timeout`.")
+    )]
+    DeviceTimeout {
+        /// How long the device was given to respond
+        timeout_ms: u128,
+    },
+
+    #[error("No V5 device found on port {0}.")]
+    #[diagnostic(
+        code(cargo_v5::no_device_on_port),
+        help("Set `CARGO_V5_PORT` to a connected device's port, or unset it to use any device.")
+    )]
+    NoDeviceOnPort(String),
+
+    #[error("Permission denied while opening the serial port {0}.")]
+    #[diagnostic(
+        code(cargo_v5::serial_permission_denied),
+        help(
+            "Add yourself to the `dialout` group (`uucp` on Arch) with `sudo usermod -aG dialout $USER`, \
+             then log out and back in. Or, install a udev rule that gives you access to VEX devices."
+        )
+    )]
+    SerialPermissionDenied(String),
+
+    #[error("The directory '{0}' does not exist.")]
+    #[diagnostic(
+        code(cargo_v5::path_not_found),
+        help("Check that `--path` points to your project's directory.")
+    )]
+    PathNotFound(Utf8PathBuf),
+
+    #[error("'{0}' is not a directory.")]
+    #[diagnostic(
+        code(cargo_v5::not_a_directory),
+        help("`--path` should point to a directory, not a file.")
+    )]
+    NotADirectory(Utf8PathBuf),
+
+    #[error("A target spec already exists at '{0}'.")]
+    #[diagnostic(
+        code(cargo_v5::target_spec_exists),
+        help("Pass `--force` to overwrite it with the built-in target spec.")
+    )]
+    TargetSpecExists(Utf8PathBuf),
+
+    #[error("Manifest '{0}' does not exist.")]
+    #[diagnostic(
+        code(cargo_v5::manifest_not_found),
+        help("Run this in your project's directory, or pass `--path` or `--manifest-path`.")
+    )]
+    ManifestNotFound(Utf8PathBuf),
+
+    #[error("`{0}` conflicts with a flag cargo-v5 passes to cargo.")]
+    #[diagnostic(
+        code(cargo_v5::managed_cargo_flag),
+        help(
+            "cargo-v5 sets `--target`, `-Zbuild-std`, and `--message-format` itself. Use `--target-spec` for a custom target, or remove `--strict` to only warn."
+        )
+    )]
+    ManagedCargoFlag(String),
+
+    #[error("No assembly listing was found for {0}.")]
+    #[diagnostic(
+        code(cargo_v5::asm_listing_not_found),
+        help(
+            "`--emit-asm` is passed to rustc through cargo's rustflags, which the RUSTFLAGS and CARGO_ENCODED_RUSTFLAGS environment variables replace. Unset them and build again."
+        )
+    )]
+    AsmListingNotFound(Utf8PathBuf),
+
+    #[error("No function named `{function}` was found in {listing}.")]
+    #[diagnostic(
+        code(cargo_v5::asm_function_not_found),
+        help(
+            "Pass the function's name or path, like `autonomous` or `my_robot::autonomous`. Functions that are always inlined have no assembly of their own, which `#[inline(never)]` prevents."
+        )
+    )]
+    AsmFunctionNotFound {
+        function: String,
+        listing: Utf8PathBuf,
+    },
+
+    #[error("'{0}' on the brain doesn't match what was uploaded.")]
+    #[diagnostic(
+        code(cargo_v5::verification_failed),
+        help(
+            "The upload was probably corrupted by a flaky connection. Try uploading again, or with a different cable."
+        )
+    )]
+    VerificationFailed(String),
+
     #[error("Output ELF file could not be parsed.")]
     #[diagnostic(code(cargo_v5::elf_parse_error))]
     ElfParseError(#[from] object::Error),
@@ -135,6 +334,55 @@ pub enum CliError {
     )]
     ProjectDirFull(String),
 
+    #[error("Could not add dependencies to the new project.")]
+    #[diagnostic(
+        code(cargo_v5::add_dependencies_failed),
+        help("The project was created. Check the `--deps` above, then add them with `cargo add`.")
+    )]
+    AddDependenciesFailed,
+
+    #[error("'{0}' is not a workspace manifest.")]
+    #[diagnostic(
+        code(cargo_v5::workspace_not_found),
+        help("Run this from the workspace's root directory, or pass it with `--path`.")
+    )]
+    WorkspaceNotFound(Utf8PathBuf),
+
+    #[error("Can't create a project in '{path}'.")]
+    #[diagnostic(
+        code(cargo_v5::project_dir_not_creatable),
+        help("Check that `--path` is a directory you're allowed to write to.")
+    )]
+    ProjectDirNotCreatable {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("The target spec '{path}' isn't a valid JSON object.")]
+    #[diagnostic(
+        code(cargo_v5::invalid_target_spec),
+        help(
+            "`--linker-script` edits the target spec, so it has to be valid JSON. Fix the spec, or start over from the built-in one with `cargo v5 target eject --force`."
+        )
+    )]
+    InvalidTargetSpec {
+        path: Utf8PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Can't write build output to '{0}'.")]
+    #[diagnostic(
+        code(cargo_v5::output_not_writable),
+        help("Pass `--output-dir` to write program binaries to a writable directory instead.")
+    )]
+    OutputNotWritable(Utf8PathBuf),
+
+    #[error(transparent)]
+    #[diagnostic(code(cargo_v5::toml_error))]
+    TomlError(#[from] toml_edit::TomlError),
+
     #[error("Program exceeded the maximum differential upload size of 2MiB (program was {}).", format_size(*.0, BINARY))]
     #[diagnostic(
         code(cargo_v5::program_too_large),
@@ -142,6 +390,21 @@ pub enum CliError {
     )]
     ProgramTooLarge(usize),
 
+    #[error(
+        "{path} is {}, which is over the {} limit.",
+        format_size(*.size, BINARY),
+        format_size(*.limit, BINARY)
+    )]
+    #[diagnostic(
+        code(cargo_v5::binary_too_large),
+        help("Run `cargo v5 build --analyze-size` to see what takes up the most space.")
+    )]
+    BinaryTooLarge {
+        path: Utf8PathBuf,
+        size: u64,
+        limit: u64,
+    },
+
     #[error("Patch exceeded the maximum size of 2MiB (patch was {}).", format_size(*.0, BINARY))]
     #[diagnostic(
         code(cargo_v5::patch_too_large),
@@ -149,7 +412,104 @@ pub enum CliError {
     )]
     PatchTooLarge(usize),
 
+    #[error(transparent)]
+    #[diagnostic(code(cargo_v5::cargo_metadata_error))]
+    CargoMetadataError(#[from] cargo_metadata::Error),
+
+    #[error("{0} required check(s) failed.")]
+    #[diagnostic(
+        code(cargo_v5::doctor_checks_failed),
+        help("Follow the hints above, then run `cargo v5 doctor` again.")
+    )]
+    DoctorChecksFailed(usize),
+
     #[error(transparent)]
     #[diagnostic(code(cargo_v5::rustc_version_error))]
     RustcVersionError(#[from] rustc_version::Error),
+
+    #[error("Interrupted.")]
+    #[diagnostic(code(cargo_v5::interrupted))]
+    Interrupted,
+}
+
+/// Process exit codes for each class of failure, so that scripts can tell them apart.
+pub mod exit_code {
+    /// Any failure that doesn't fit one of the other classes.
+    pub const FAILURE: i32 = 1;
+    /// An argument, environment variable, or manifest field was invalid.
+    pub const INVALID_INPUT: i32 = 2;
+    /// The installed Rust toolchain can't build vexide projects.
+    pub const TOOLCHAIN: i32 = 3;
+    /// `cargo build` failed, or produced nothing to upload.
+    pub const BUILD_FAILED: i32 = 4;
+    /// No suitable V5 device was connected, or it stopped responding.
+    pub const DEVICE: i32 = 5;
+    /// The project template couldn't be downloaded.
+    pub const TEMPLATE_FETCH: i32 = 6;
+    /// The command was cancelled with Ctrl-C, following the shell's `128 + SIGINT`.
+    pub const INTERRUPTED: i32 = 130;
+}
+
+impl CliError {
+    /// The process exit code `cargo v5` should exit with when failing with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::BadFieldType { .. }
+            | Self::SlotOutOfRange
+            | Self::EraseNotConfirmed
+            | Self::InvalidSlot(_)
+            | Self::InvalidAuthor(_)
+            | Self::InvalidIcon(_)
+            | Self::InvalidUploadStrategy(_)
+            | Self::InvalidEnvVar { .. }
+            | Self::NoSlot
+            | Self::NoProjectName
+            | Self::NoPrograms
+            | Self::PathNotFound(_)
+            | Self::NotADirectory(_)
+            | Self::ManifestNotFound(_)
+            | Self::ProjectDirFull(_)
+            | Self::ProjectDirNotCreatable { .. }
+            | Self::TargetSpecExists(_)
+            | Self::InvalidTargetSpec { .. }
+            | Self::MultipleArtifacts(_)
+            | Self::ManagedCargoFlag(_)
+            | Self::AsmListingNotFound(_)
+            | Self::AsmFunctionNotFound { .. }
+            | Self::WorkspaceNotFound(_)
+            | Self::TomlError(_) => exit_code::INVALID_INPUT,
+
+            Self::NightlyRequired | Self::RustcVersionError(_) | Self::DoctorChecksFailed(_) => {
+                exit_code::TOOLCHAIN
+            }
+
+            Self::BuildFailed
+            | Self::NoArtifact
+            | Self::BinaryTooLarge { .. }
+            | Self::CargoMetadataError(_) => exit_code::BUILD_FAILED,
+
+            Self::SerialError(_)
+            | Self::Nack(_)
+            | Self::SlotUploadsFailed(_)
+            | Self::ProgramRunning(_)
+            | Self::NoDevice
+            | Self::NoDeviceOnPort(_)
+            | Self::SerialPermissionDenied(_)
+            | Self::VerificationFailed(_)
+            | Self::DeviceTimeout { .. }
+            | Self::RadioChannelDisconnectTimeout
+            | Self::RadioChannelReconnectTimeout => exit_code::DEVICE,
+            #[cfg(feature = "field-control")]
+            Self::NoController => exit_code::DEVICE,
+
+            #[cfg(feature = "fetch-template")]
+            Self::ReqwestError(_) | Self::MalformedResponse | Self::GitHubRateLimited { .. } => {
+                exit_code::TEMPLATE_FETCH
+            }
+
+            Self::Interrupted => exit_code::INTERRUPTED,
+
+            _ => exit_code::FAILURE,
+        }
+    }
 }
@@ -1,4 +1,18 @@
+//! Build, upload, and run vexide projects on the VEX V5.
+//!
+//! `cargo-v5` can also be used as a library. The CLI is a thin wrapper around these
+//! functions, which all return [`errors::CliError`]:
+//!
+//! - [`commands::build::build`] and [`commands::build::build_workspace`] build projects
+//!   and convert their executables to binaries.
+//! - [`commands::upload::upload`] builds a project and uploads it, while
+//!   [`commands::upload::upload_build_output`] uploads the output of a previous build.
+//! - [`commands::new::new`] creates a new project from vexide-template.
+//! - [`connection::open_connection`] opens a connection to a V5 brain or controller for
+//!   the device commands in [`commands`].
+
 pub mod commands;
+pub mod config;
 pub mod connection;
 pub mod errors;
 pub mod metadata;
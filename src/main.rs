@@ -1,30 +1,38 @@
 use core::panic;
-#[cfg(feature = "field-control")]
-use std::time::Duration;
-use std::{env, num::NonZeroU32, path::PathBuf};
+use std::{env, num::NonZeroU32, path::PathBuf, time::Duration};
 
 use cargo_metadata::camino::Utf8PathBuf;
 #[cfg(feature = "field-control")]
-use cargo_v5::{commands::field_control::run_field_control_tui, errors::CliError};
+use cargo_v5::commands::field_control::run_field_control_tui;
 use cargo_v5::{
     commands::{
-        build::{CargoOpts, build},
+        build::{CargoOpts, build, build_workspace, single_output, strip_disabled},
         cat::cat,
         devices::devices,
         dir::dir,
+        doctor::doctor,
         log::log,
-        new::new,
-        rm::rm,
+        new::{DownloadOpts, NewOpts, new, project_name},
+        rm::{rm, rm_slot},
         screenshot::screenshot,
+        size::{SizeOpts, analyze_size},
+        slots::slots,
+        target::{eject, install},
         terminal::terminal,
-        upload::{AfterUpload, UploadOpts, upload},
+        test::{TestOpts, test},
+        upload::{AfterUpload, UploadOpts, upload, upload_build_output, upload_programs},
+        version::version,
+        watch::{WatchAction, watch},
     },
+    config::{self, EnvConfig},
     connection::{open_connection, switch_radio_channel},
+    errors::{CliError, exit_code},
     self_update::{self, SelfUpdateMode},
 };
 use chrono::Utc;
-use clap::{Args, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use flexi_logger::{AdaptiveFormat, FileSpec, LogfileSelector, LoggerHandle};
+use miette::WrapErr;
 #[cfg(feature = "field-control")]
 use vex_v5_serial::connection::serial::{self, SerialConnection, SerialDevice};
 use vex_v5_serial::{
@@ -48,8 +56,17 @@ enum Cargo {
         #[command(subcommand)]
         command: Command,
 
-        #[arg(long, default_value = ".", global = true)]
-        path: Utf8PathBuf,
+        /// Path to the project [default: $CARGO_MANIFEST_DIR, or .]
+        #[arg(long, global = true)]
+        path: Option<Utf8PathBuf>,
+
+        /// How long to wait for a device to respond when connecting, in milliseconds.
+        #[arg(long, value_name = "MS", default_value_t = 5000, global = true)]
+        timeout: u64,
+
+        /// Never prompt for input: use defaults, and confirm anything that asks.
+        #[arg(short, long, global = true)]
+        yes: bool,
     },
 }
 
@@ -59,6 +76,52 @@ enum Command {
     /// Build a project for the V5 brain.
     #[clap(visible_alias = "b")]
     Build {
+        /// Build every package in the workspace with a `package.metadata.v5` table.
+        #[arg(long)]
+        workspace: bool,
+
+        /// Upload the program to the brain once it builds successfully.
+        ///
+        /// Upload options are read from `package.metadata.v5`.
+        #[arg(
+            long,
+            visible_alias = "upload-after-build",
+            conflicts_with = "workspace"
+        )]
+        upload: bool,
+
+        /// Print the cargo command that would be run, and the environment variables that
+        /// affect it, instead of building.
+        #[arg(long, conflicts_with = "upload")]
+        dry_run: bool,
+
+        /// Show cargo's verbose output as-is, for debugging. The program isn't converted to a
+        /// `.bin` file, since cargo's plain output doesn't say where it is.
+        #[arg(long, conflicts_with = "upload")]
+        cargo_verbose: bool,
+
+        /// Keep building other packages and targets after one fails to build.
+        #[arg(long)]
+        keep_going: bool,
+
+        /// Leave the program as an ELF instead of converting it to a `.bin` file.
+        ///
+        /// Can also be set with `package.metadata.v5.strip = false`, which uploads ignore.
+        #[arg(long, conflicts_with = "upload")]
+        no_strip: bool,
+
+        /// Also write an assembly listing of the program, and print where it is.
+        #[arg(long)]
+        emit_asm: bool,
+
+        /// Print the assembly of only this function, like `autonomous` or
+        /// `my_robot::autonomous`.
+        #[arg(long, value_name = "NAME", requires = "emit_asm")]
+        function: Option<String>,
+
+        #[clap(flatten)]
+        size_opts: SizeOpts,
+
         /// Arguments forwarded to `cargo`.
         #[clap(flatten)]
         cargo_opts: CargoOpts,
@@ -66,39 +129,78 @@ enum Command {
     /// Build a project and upload it to the V5 brain.
     #[clap(visible_alias = "u")]
     Upload {
-        #[arg(long, default_value = "none")]
+        /// What the brain should do once the upload finishes.
+        #[arg(long, value_enum, default_value = "none")]
         after: AfterUpload,
 
         #[clap(flatten)]
         upload_opts: UploadOpts,
     },
+    /// Rebuild a project whenever its sources change.
+    #[clap(visible_alias = "w")]
+    Watch {
+        /// Also run this command after each successful build.
+        #[arg(long, value_enum)]
+        and: Option<WatchAction>,
+
+        #[clap(flatten)]
+        upload_opts: UploadOpts,
+    },
+    /// Run a project's tests on this computer instead of the brain.
+    ///
+    /// Only code that doesn't need the brain (or vexide's runtime) can be tested like this.
+    Test {
+        #[clap(flatten)]
+        opts: TestOpts,
+    },
     /// Access the brain's remote terminal I/O.
     #[clap(visible_alias = "t")]
     Terminal,
     /// Build, upload, and run a program on the V5 brain, showing its output in the terminal.
     #[clap(visible_alias = "r")]
-    Run(UploadOpts),
+    Run {
+        /// Upload every program in `package.metadata.v5.programs` to its slot, then run the
+        /// last one.
+        #[arg(long, conflicts_with_all = ["slot", "name", "file"])]
+        all: bool,
+
+        #[clap(flatten)]
+        opts: UploadOpts,
+    },
     /// Create a new vexide project with a given name.
     #[clap(visible_alias = "n")]
     New {
-        /// The name of the project.
-        name: String,
+        /// The name of the project. Prompted for if it isn't given.
+        name: Option<String>,
 
         #[clap(flatten)]
-        download_opts: DownloadOpts,
+        opts: NewOpts,
     },
     /// Creates a new vexide project in the current directory
     Init {
         #[clap(flatten)]
-        download_opts: DownloadOpts,
+        opts: NewOpts,
     },
     /// List files on flash.
     #[clap(visible_alias = "ls")]
     Dir,
+    /// List the program installed in each slot.
+    Slots,
     /// Read a file from flash, then write its contents to stdout.
     Cat { file: PathBuf },
     /// Erase a file from flash.
-    Rm { file: PathBuf },
+    Rm {
+        #[arg(required_unless_present = "slot")]
+        file: Option<PathBuf>,
+
+        /// Erase the program in this slot instead of a file.
+        #[arg(long, value_name = "1-8", conflicts_with = "file")]
+        slot: Option<u8>,
+
+        /// Erase the program in `--slot` without asking for confirmation.
+        #[arg(long)]
+        force: bool,
+    },
     /// Read event log.
     Log {
         #[arg(long, short, default_value = "1")]
@@ -106,7 +208,15 @@ enum Command {
     },
     /// List devices connected to a brain.
     #[clap(visible_alias = "lsdev")]
-    Devices,
+    Devices {
+        /// Print each device as a JSON object on its own line.
+        #[arg(long)]
+        json: bool,
+
+        /// Keep polling the brain, printing the devices again whenever they change.
+        #[arg(long)]
+        watch: bool,
+    },
     /// Take a screen capture of the brain, saving the file to the current directory.
     #[clap(visible_alias = "sc")]
     Screenshot,
@@ -114,23 +224,72 @@ enum Command {
     #[cfg(feature = "field-control")]
     #[clap(visible_aliases = ["fc", "comp-control"])]
     FieldControl,
+    /// Manage the V5 target spec.
+    Target {
+        #[command(subcommand)]
+        command: TargetCommand,
+    },
+    /// Check that everything needed to build and upload projects is set up.
+    Doctor,
+    /// Print the version of cargo-v5 and of the project's Rust toolchain.
+    Version {
+        /// Print the versions as a JSON object.
+        #[arg(long)]
+        json: bool,
+    },
     /// Update cargo-v5 to the latest version.
     #[clap(hide = matches!(*self_update::CURRENT_MODE, SelfUpdateMode::Unmanaged(_)))]
     SelfUpdate,
 }
 
-#[derive(Args, Debug)]
-struct DownloadOpts {
-    /// Do not download the latest template online.
-    #[cfg_attr(feature = "fetch-template", arg(long, default_value = "false"))]
-    #[cfg_attr(not(feature = "fetch-template"), arg(skip = false))]
-    offline: bool,
+/// Prints `err` and exits with the code for its class of failure.
+fn exit_with_error(err: miette::Report) -> ! {
+    let code = err
+        .downcast_ref::<CliError>()
+        .map(CliError::exit_code)
+        .unwrap_or(exit_code::FAILURE);
+    eprintln!("Error: {err:?}");
+    std::process::exit(code);
+}
+
+/// A `cargo v5 target` subcommand.
+#[derive(Subcommand, Debug)]
+enum TargetCommand {
+    /// Write the built-in target spec to a file so it can be customized.
+    ///
+    /// Builds use `v5-target/armv7a-vex-v5.json` instead of the built-in spec when it exists.
+    Eject {
+        /// Where to write the target spec [default: v5-target/armv7a-vex-v5.json]
+        #[arg(long, short)]
+        output: Option<Utf8PathBuf>,
+
+        /// Overwrite an existing target spec.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write the built-in target spec to `.cargo/` and make it the project's build target.
+    ///
+    /// This sets `build.target` in `.cargo/config.toml`, so plain `cargo build` (and tools
+    /// like rust-analyzer) build for the brain. Builds use the installed spec as-is.
+    Install {
+        /// Overwrite an existing target spec.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[tokio::main]
-async fn main() -> miette::Result<()> {
+async fn main() {
+    // Load `CARGO_V5_*` overrides first. These only act as defaults, so CLI arguments still win.
+    let env_config = config::from_env().unwrap_or_else(|err| exit_with_error(err.into()));
+
     // Parse CLI arguments
-    let Cargo::V5 { command, path } = Cargo::parse();
+    let Cargo::V5 {
+        command,
+        path,
+        timeout,
+        yes,
+    } = Cargo::parse();
 
     let mut logger = flexi_logger::Logger::try_with_env()
         .unwrap()
@@ -148,34 +307,176 @@ async fn main() -> miette::Result<()> {
         .start()
         .unwrap();
 
-    if let Err(err) = app(command, path, &mut logger).await {
+    let timeout = Duration::from_millis(timeout);
+    let path = path
+        .or_else(|| env_config.manifest_dir.clone())
+        .unwrap_or_else(|| Utf8PathBuf::from("."));
+    if let Err(err) = app(command, path, timeout, yes, env_config, &mut logger).await {
         log::debug!("cargo-v5 is exiting due to an error: {}", err);
         if let Ok(files) = logger.existing_log_files(&LogfileSelector::default()) {
             for file in files {
                 eprintln!("A log file is available at {}.", file.display());
             }
         }
-        return Err(err);
+        exit_with_error(err);
     }
-    Ok(())
 }
 
-async fn app(command: Command, path: Utf8PathBuf, logger: &mut LoggerHandle) -> miette::Result<()> {
+fn apply_env_download_opts(opts: &mut DownloadOpts, env_config: &EnvConfig) {
+    opts.offline |= env_config.offline.unwrap_or(false);
+    // `--template-tag` and `--branch` conflict with `--template-url`, so they also override the
+    // env var.
+    if opts.template_tag.is_none() && opts.branch.is_none() {
+        opts.template_url = opts.template_url.take().or(env_config.template_url.clone());
+    }
+    opts.template_cache_ttl = opts.template_cache_ttl.or(env_config.template_cache_ttl);
+}
+
+fn apply_env_cargo_opts(opts: &mut CargoOpts, env_config: &EnvConfig) {
+    opts.no_nightly_check |= env_config.no_nightly_check.unwrap_or(false);
+}
+
+fn apply_env_upload_opts(
+    opts: &mut UploadOpts,
+    env_config: &EnvConfig,
+    timeout: Duration,
+    yes: bool,
+) {
+    apply_env_cargo_opts(&mut opts.cargo_opts, env_config);
+    opts.timeout = Some(timeout);
+    opts.port = env_config.port.clone();
+    opts.yes = yes;
+    if opts.slot.is_empty() {
+        opts.slot.extend(env_config.slot);
+    }
+    opts.icon = opts.icon.or(env_config.icon);
+    opts.upload_strategy = opts.upload_strategy.or(env_config.upload_strategy);
+}
+
+/// Runs `operation`, dropping it if Ctrl-C is pressed first.
+///
+/// Dropping the future cancels any in-flight downloads, and the template cache is only ever
+/// replaced atomically, so an interrupted command can't leave it half-written.
+async fn cancel_on_ctrl_c<T>(
+    operation: impl Future<Output = Result<T, CliError>>,
+) -> Result<T, CliError> {
+    tokio::select! {
+        result = operation => result,
+        _ = tokio::signal::ctrl_c() => Err(CliError::Interrupted),
+    }
+}
+
+async fn app(
+    command: Command,
+    path: Utf8PathBuf,
+    timeout: Duration,
+    yes: bool,
+    env_config: EnvConfig,
+    logger: &mut LoggerHandle,
+) -> miette::Result<()> {
+    // Catch a mistyped `--path` before cargo or the template unpacker trip over it.
+    match command {
+        Command::Build { .. }
+        | Command::Upload { .. }
+        | Command::Run { .. }
+        | Command::Watch { .. }
+        | Command::Test { .. } => {
+            if !path.exists() {
+                Err(CliError::PathNotFound(path.clone()))?;
+            }
+            if !path.is_dir() {
+                Err(CliError::NotADirectory(path.clone()))?;
+            }
+        }
+        Command::New { .. } | Command::Init { .. } if path.exists() && !path.is_dir() => {
+            Err(CliError::NotADirectory(path.clone()))?;
+        }
+        _ => {}
+    }
+
+    // Connects to the device on `CARGO_V5_PORT`, or the first one found.
+    let connect = || open_connection(env_config.port.clone(), timeout);
+
     match command {
-        Command::Build { cargo_opts } => {
-            build(&path, cargo_opts).await?;
+        Command::Build {
+            workspace,
+            upload,
+            dry_run,
+            cargo_verbose,
+            keep_going,
+            no_strip,
+            emit_asm,
+            function,
+            size_opts,
+            mut cargo_opts,
+        } => {
+            apply_env_cargo_opts(&mut cargo_opts, &env_config);
+            cargo_opts.dry_run = dry_run;
+            cargo_opts.cargo_verbose = cargo_verbose;
+            cargo_opts.keep_going = keep_going;
+            cargo_opts.emit_asm = emit_asm;
+            cargo_opts.asm_function = function;
+            cargo_opts.no_strip = no_strip
+                || (!upload && !workspace && strip_disabled(&cargo_opts.manifest_path(&path))?);
+            if workspace {
+                let outputs = build_workspace(&path, cargo_opts).await?;
+                if size_opts.analyze_size {
+                    for output in &outputs {
+                        analyze_size(&output.elf_artifact, &size_opts)?;
+                    }
+                }
+            } else {
+                let outputs = build(&path, cargo_opts).await?;
+                if size_opts.analyze_size {
+                    for output in &outputs {
+                        analyze_size(&output.elf_artifact, &size_opts)?;
+                    }
+                }
+                if upload {
+                    let output = single_output(outputs)?;
+                    let mut upload_opts = UploadOpts::default();
+                    apply_env_upload_opts(&mut upload_opts, &env_config, timeout, yes);
+                    upload_build_output(output, upload_opts, AfterUpload::None)
+                        .await
+                        .wrap_err("The build succeeded, but uploading failed")?;
+                }
+            }
         }
-        Command::Upload { upload_opts, after } => {
+        Command::Upload {
+            mut upload_opts,
+            after,
+        } => {
+            apply_env_upload_opts(&mut upload_opts, &env_config, timeout, yes);
             upload(&path, upload_opts, after).await?;
         }
-        Command::Dir => dir(&mut open_connection().await?).await?,
-        Command::Devices => devices(&mut open_connection().await?).await?,
-        Command::Cat { file } => cat(&mut open_connection().await?, file).await?,
-        Command::Rm { file } => rm(&mut open_connection().await?, file).await?,
-        Command::Log { page } => log(&mut open_connection().await?, page).await?,
-        Command::Screenshot => screenshot(&mut open_connection().await?).await?,
-        Command::Run(opts) => {
-            let mut connection = upload(&path, opts, AfterUpload::Run).await?;
+        Command::Watch {
+            and,
+            mut upload_opts,
+        } => {
+            apply_env_upload_opts(&mut upload_opts, &env_config, timeout, yes);
+            watch(&path, upload_opts, and).await;
+        }
+        Command::Dir => dir(&mut connect().await?).await?,
+        Command::Slots => slots(&mut connect().await?).await?,
+        Command::Devices { json, watch } => devices(&mut connect().await?, json, watch).await?,
+        Command::Cat { file } => cat(&mut connect().await?, file).await?,
+        Command::Rm { file, slot, force } => {
+            let mut connection = connect().await?;
+            match (file, slot) {
+                (_, Some(slot)) => rm_slot(&mut connection, slot, force || yes).await?,
+                (Some(file), None) => rm(&mut connection, file).await?,
+                (None, None) => unreachable!("clap requires a file or a slot"),
+            }
+        }
+        Command::Log { page } => log(&mut connect().await?, page).await?,
+        Command::Screenshot => screenshot(&mut connect().await?).await?,
+        Command::Run { all, mut opts } => {
+            apply_env_upload_opts(&mut opts, &env_config, timeout, yes);
+            let mut connection = if all {
+                upload_programs(&path, opts, AfterUpload::Run).await?
+            } else {
+                upload(&path, opts, AfterUpload::Run).await?
+            };
 
             tokio::select! {
                 () = terminal(&mut connection, logger) => {}
@@ -197,7 +498,7 @@ async fn app(command: Command, path: Utf8PathBuf, logger: &mut LoggerHandle) ->
             }
         }
         Command::Terminal => {
-            let mut connection = open_connection().await?;
+            let mut connection = connect().await?;
             switch_radio_channel(&mut connection, RadioChannel::Download).await?;
             terminal(&mut connection, logger).await;
         }
@@ -214,7 +515,7 @@ async fn app(command: Command, path: Utf8PathBuf, logger: &mut LoggerHandle) ->
                             matches!(device, SerialDevice::Controller { system_port: _ })
                         })
                         .ok_or(CliError::NoController)?
-                        .connect(Duration::from_secs(5))
+                        .connect(timeout)
                         .map_err(CliError::SerialError)?)
                 })
                 .await
@@ -223,15 +524,28 @@ async fn app(command: Command, path: Utf8PathBuf, logger: &mut LoggerHandle) ->
 
             run_field_control_tui(&mut connection).await?;
         }
-        Command::New {
-            name,
-            download_opts,
-        } => {
-            new(path, Some(name), !download_opts.offline).await?;
+        Command::New { name, mut opts } => {
+            apply_env_download_opts(&mut opts.download_opts, &env_config);
+            apply_env_cargo_opts(&mut opts.cargo_opts, &env_config);
+            opts.yes = yes;
+            let name = project_name(name, &opts)?;
+            cancel_on_ctrl_c(new(path, Some(name), opts)).await?;
         }
-        Command::Init { download_opts } => {
-            new(path, None, !download_opts.offline).await?;
+        Command::Init { mut opts } => {
+            apply_env_download_opts(&mut opts.download_opts, &env_config);
+            apply_env_cargo_opts(&mut opts.cargo_opts, &env_config);
+            opts.yes = yes;
+            cancel_on_ctrl_c(new(path, None, opts)).await?;
         }
+        Command::Target {
+            command: TargetCommand::Eject { output, force },
+        } => eject(&path, output.as_deref(), force).await?,
+        Command::Target {
+            command: TargetCommand::Install { force },
+        } => install(&path, force).await?,
+        Command::Test { opts } => test(&path, opts)?,
+        Command::Doctor => doctor(&path)?,
+        Command::Version { json } => version(&path, json),
         Command::SelfUpdate => {
             self_update::self_update().await?;
         }
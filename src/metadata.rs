@@ -18,44 +18,90 @@ fn field_type(field: &Value) -> &'static str {
     }
 }
 
-#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+fn parse_slot(field: &Value, name: &str) -> Result<u8, CliError> {
+    let slot = field.as_u64().ok_or(CliError::BadFieldType {
+        field: name.to_string(),
+        expected: "number".to_string(),
+        found: field_type(field).to_string(),
+    })?;
+
+    Ok(slot as u8) // NOTE: range validation is done at a later step
+}
+
+fn parse_icon(field: &Value, name: &str) -> Result<ProgramIcon, CliError> {
+    let icon = field.as_str().ok_or(CliError::BadFieldType {
+        field: name.to_string(),
+        expected: "string".to_string(),
+        found: field_type(field).to_string(),
+    })?;
+
+    ProgramIcon::from_str(icon, false).map_err(|_| CliError::InvalidIcon(icon.to_string()))
+}
+
+fn parse_string(field: &Value, name: &str) -> Result<String, CliError> {
+    field
+        .as_str()
+        .map(str::to_string)
+        .ok_or(CliError::BadFieldType {
+            field: name.to_string(),
+            expected: "string".to_string(),
+            found: field_type(field).to_string(),
+        })
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct Metadata {
     pub slot: Option<u8>,
     pub icon: Option<ProgramIcon>,
+    /// Program name from `package.metadata.v5.program.name`.
+    pub name: Option<String>,
+    /// Program description from `package.metadata.v5.program.description`.
+    pub description: Option<String>,
     pub compress: Option<bool>,
     pub upload_strategy: Option<UploadStrategy>,
+    pub strip: Option<bool>,
 }
 
 impl Metadata {
     pub fn new(pkg: &Package) -> Result<Self, CliError> {
         if let Some(metadata) = pkg.metadata.as_object() {
             if let Some(v5_metadata) = metadata.get("v5").and_then(|m| m.as_object()) {
-                return Ok(Self {
-                    slot: if let Some(field) = v5_metadata.get("slot") {
-                        let slot = field.as_u64().ok_or(CliError::BadFieldType {
-                            field: "slot".to_string(),
-                            expected: "string".to_string(),
+                // The `program` table describes the uploaded program, and takes precedence
+                // over the `slot` and `icon` set directly in `package.metadata.v5`.
+                let program = match v5_metadata.get("program") {
+                    Some(Value::Object(program)) => Some(program),
+                    Some(field) => {
+                        return Err(CliError::BadFieldType {
+                            field: "program".to_string(),
+                            expected: "table".to_string(),
                             found: field_type(field).to_string(),
-                        })?;
+                        });
+                    }
+                    None => None,
+                };
+                let program_field = |key: &str| program.and_then(|program| program.get(key));
 
-                        Some(slot as u8) // NOTE: range validation is done at a later step
+                return Ok(Self {
+                    slot: if let Some(field) = program_field("slot") {
+                        Some(parse_slot(field, "program.slot")?)
+                    } else if let Some(field) = v5_metadata.get("slot") {
+                        Some(parse_slot(field, "slot")?)
                     } else {
                         None
                     },
-                    icon: if let Some(field) = v5_metadata.get("icon") {
-                        let icon = field.as_str().ok_or(CliError::BadFieldType {
-                            field: "icon".to_string(),
-                            expected: "string".to_string(),
-                            found: field_type(field).to_string(),
-                        })?;
-
-                        Some(
-                            ProgramIcon::from_str(icon, false)
-                                .map_err(|_| CliError::InvalidIcon(icon.to_string()))?,
-                        )
+                    icon: if let Some(field) = program_field("icon") {
+                        Some(parse_icon(field, "program.icon")?)
+                    } else if let Some(field) = v5_metadata.get("icon") {
+                        Some(parse_icon(field, "icon")?)
                     } else {
                         None
                     },
+                    name: program_field("name")
+                        .map(|field| parse_string(field, "program.name"))
+                        .transpose()?,
+                    description: program_field("description")
+                        .map(|field| parse_string(field, "program.description"))
+                        .transpose()?,
                     compress: if let Some(compress) = v5_metadata.get("compress") {
                         let compress = compress.as_bool().ok_or(CliError::BadFieldType {
                             field: "compress".to_string(),
@@ -84,6 +130,17 @@ impl Metadata {
                     } else {
                         None
                     },
+                    strip: if let Some(strip) = v5_metadata.get("strip") {
+                        let strip = strip.as_bool().ok_or(CliError::BadFieldType {
+                            field: "strip".to_string(),
+                            expected: "bool".to_string(),
+                            found: field_type(strip).to_string(),
+                        })?;
+
+                        Some(strip)
+                    } else {
+                        None
+                    },
                 });
             }
         }
@@ -91,3 +148,61 @@ impl Metadata {
         Ok(Self::default())
     }
 }
+
+/// A program listed in the `[[package.metadata.v5.programs]]` array, which `cargo v5 run --all`
+/// builds and uploads to its own slot.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgramMetadata {
+    /// Name of the binary target to build.
+    pub bin: String,
+    pub slot: u8,
+    /// Program name shown on the brain, defaulting to the binary's name.
+    pub name: Option<String>,
+}
+
+impl ProgramMetadata {
+    /// Reads the programs listed in `pkg`'s `package.metadata.v5.programs` array.
+    pub fn list(pkg: &Package) -> Result<Vec<Self>, CliError> {
+        let Some(programs) = pkg.metadata.get("v5").and_then(|m| m.get("programs")) else {
+            return Ok(Vec::new());
+        };
+        let programs = programs.as_array().ok_or(CliError::BadFieldType {
+            field: "programs".to_string(),
+            expected: "array".to_string(),
+            found: field_type(programs).to_string(),
+        })?;
+
+        programs
+            .iter()
+            .enumerate()
+            .map(|(i, program)| {
+                let string_field = |key: &str| {
+                    let field = &program[key];
+                    field
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or(CliError::BadFieldType {
+                            field: format!("programs[{i}].{key}"),
+                            expected: "string".to_string(),
+                            found: field_type(field).to_string(),
+                        })
+                };
+
+                let slot = &program["slot"];
+                Ok(Self {
+                    bin: string_field("bin")?,
+                    slot: slot.as_u64().ok_or(CliError::BadFieldType {
+                        field: format!("programs[{i}].slot"),
+                        expected: "number".to_string(),
+                        found: field_type(slot).to_string(),
+                    })? as u8, // NOTE: range validation is done at a later step
+                    name: if program["name"].is_null() {
+                        None
+                    } else {
+                        Some(string_field("name")?)
+                    },
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,59 @@
+//! Builds a fixture project for the V5 target.
+//!
+//! This needs a nightly toolchain with `rust-src`, like any vexide project. The test is
+//! skipped if one isn't installed.
+
+use std::path::Path;
+
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_v5::commands::build::{CargoOpts, build, is_nightly_toolchain};
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let to = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to);
+        } else {
+            std::fs::copy(entry.path(), to).unwrap();
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn build_produces_bin() {
+    // The fixture pins nightly in its `rust-toolchain.toml`, which is ignored if the toolchain
+    // running these tests is passed down through `RUSTUP_TOOLCHAIN` or `CARGO`.
+    // SAFETY: This is the only test in this binary, so nothing else reads the environment.
+    unsafe {
+        std::env::remove_var("RUSTUP_TOOLCHAIN");
+        std::env::remove_var("CARGO");
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = Utf8PathBuf::from_path_buf(dir.path().join("minimal")).unwrap();
+    copy_dir(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/minimal"),
+        path.as_std_path(),
+    );
+
+    if !is_nightly_toolchain(&path) {
+        eprintln!("skipping: no nightly toolchain is installed");
+        return;
+    }
+
+    let mut opts = CargoOpts::default();
+    // Newer nightlies only accept JSON target specs with `-Zjson-target-spec`.
+    opts.configs
+        .push("unstable.json-target-spec=true".to_string());
+    let mut outputs = build(&path, opts).await.unwrap();
+    assert_eq!(outputs.len(), 1, "the build should produce one executable");
+    let output = outputs.remove(0);
+
+    assert_eq!(output.manifest_path, path.join("Cargo.toml"));
+    assert_eq!(output.bin_artifact.extension(), Some("bin"));
+    assert!(output.elf_artifact.exists());
+    let bin = std::fs::read(&output.bin_artifact).unwrap();
+    assert!(!bin.is_empty());
+}
@@ -0,0 +1,7 @@
+fn main() {
+    // The V5 target spec links with `v5.ld`, which vexide projects get from vexide-startup.
+    println!(
+        "cargo:rustc-link-search={}",
+        std::env::var("CARGO_MANIFEST_DIR").unwrap()
+    );
+}
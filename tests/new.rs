@@ -0,0 +1,48 @@
+//! Creates projects from the built-in template.
+
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_v5::{
+    commands::new::{DownloadOpts, NewOpts, Vcs, new},
+    errors::CliError,
+};
+
+fn new_opts() -> NewOpts {
+    NewOpts {
+        vcs: Vcs::None,
+        yes: true,
+        download_opts: DownloadOpts {
+            offline: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_creates_project() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+    new(path.clone(), Some("robot".to_string()), new_opts())
+        .await
+        .unwrap();
+
+    let manifest = std::fs::read_to_string(path.join("robot/Cargo.toml")).unwrap();
+    let manifest = manifest.parse::<toml_edit::DocumentMut>().unwrap();
+    assert_eq!(manifest["package"]["name"].as_str(), Some("robot"));
+    assert!(path.join("robot/src/main.rs").exists());
+    assert!(!path.join("robot/.git").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_refuses_non_empty_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+    std::fs::create_dir(path.join("robot")).unwrap();
+    std::fs::write(path.join("robot/notes.txt"), "").unwrap();
+
+    let err = new(path, Some("robot".to_string()), new_opts())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CliError::ProjectDirFull(_)));
+}
@@ -0,0 +1,63 @@
+//! Checks that `cargo v5 new` downloads templates through `HTTP_PROXY`/`HTTPS_PROXY`.
+
+#![cfg(feature = "fetch-template")]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+};
+
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_v5::commands::new::{DownloadOpts, NewOpts, Vcs, new};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_downloads_through_proxy() {
+    // A proxy that records the first request line and refuses to serve anything.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+    let proxy = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request_line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .unwrap();
+        stream
+            .write_all(b"HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        request_line
+    });
+
+    // SAFETY: This is the only test in this binary, so nothing else reads the environment.
+    unsafe {
+        std::env::remove_var("NO_PROXY");
+        std::env::remove_var("no_proxy");
+        std::env::set_var("HTTP_PROXY", &proxy_url);
+        std::env::set_var("HTTPS_PROXY", &proxy_url);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+    let opts = NewOpts {
+        vcs: Vcs::None,
+        yes: true,
+        download_opts: DownloadOpts {
+            template_url: Some("http://template.invalid/vexide-template.tar.gz".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // The download fails, and a custom template isn't replaced by the built-in one.
+    assert!(
+        new(path.clone(), Some("robot".to_string()), opts)
+            .await
+            .is_err()
+    );
+    assert!(!path.join("robot/Cargo.toml").exists());
+
+    let request_line = proxy.join().unwrap();
+    assert!(
+        request_line.starts_with("GET http://template.invalid/vexide-template.tar.gz "),
+        "unexpected proxy request: {request_line:?}"
+    );
+}